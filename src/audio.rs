@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use id3::TagLike;
+
+// Tags of an audio file by lowercase name. The common fields have the same name in every format:
+// title, artist, album, albumartist, track, disc, year, genre and composer.
+pub type AudioTags = HashMap<String, String>;
+
+// The Ogg comment packet can embed cover images, files with a bigger one are not read.
+const MAX_OGG_COMMENT_SIZE: usize = 16 * 1024 * 1024;
+
+// Reads ID3v2 or ID3v1 from MP3, Vorbis comments from FLAC, Ogg Vorbis and Opus, and the iTunes atoms of M4A/MP4.
+// None when the format is not known or the file has no tags.
+pub fn read_tags(file_path: &Path) -> Option<AudioTags>{
+    let mut file = fs::File::open(file_path).ok()?;
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic).ok()?;
+    file.seek(SeekFrom::Start(0)).ok()?;
+    let is_mp3 = file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"));
+    let tags = if &magic[0..4] == b"fLaC"{
+        read_flac_comments(&mut file).ok()?
+    }
+    else if &magic[0..4] == b"OggS"{
+        read_ogg_comments(&mut file).ok()?
+    }
+    else if &magic[4..8] == b"ftyp"{
+        read_mp4_atoms(&mut file).ok()?
+    }
+    else if &magic[0..3] == b"ID3" || is_mp3{
+        read_id3(file)?
+    }
+    else{
+        return None;
+    };
+    if tags.is_empty(){
+        return None;
+    }
+    Some(tags)
+}
+
+fn insert(tags: &mut AudioTags, name: &str, value: &str){
+    let value = value.trim_matches(|character: char| character == '\0' || character.is_whitespace());
+    if !value.is_empty(){
+        tags.entry(name.to_string()).or_insert(value.to_string());
+    }
+}
+
+// "3/12" is the third track of twelve, "2024-01-31" is a date in 2024.
+fn normalize_common_fields(tags: &mut AudioTags){
+    for name in ["track", "disc"]{
+        if let Some(value) = tags.get_mut(name){
+            if let Some((number, _)) = value.split_once('/'){
+                *value = number.trim().to_string();
+            }
+        }
+    }
+    if let Some(year) = tags.get_mut("year"){
+        if year.len() > 4 && year.is_char_boundary(4) && year[..4].chars().all(|character| character.is_ascii_digit()){
+            year.truncate(4);
+        }
+    }
+}
+
+fn read_id3(file: fs::File) -> Option<AudioTags>{
+    let tag = id3::v1v2::read_from(file).ok()?;
+    let mut tags = AudioTags::new();
+    // ID3v1 genres and "(17)" are numbers of the standard genre list
+    let genre = tag.genre_parsed();
+    let text_fields = [
+        ("title", tag.title()),
+        ("artist", tag.artist()),
+        ("album", tag.album()),
+        ("albumartist", tag.album_artist()),
+        ("genre", genre.as_deref()),
+        ("composer", tag.get("TCOM").and_then(|frame| frame.content().text())),
+    ];
+    for (name, value) in text_fields{
+        if let Some(value) = value{
+            insert(&mut tags, name, value);
+        }
+    }
+    let number_fields = [
+        ("track", tag.track()),
+        ("disc", tag.disc()),
+        ("year", tag.year().map(|year| year as u32).or(tag.date_recorded().map(|date| date.year as u32))),
+    ];
+    for (name, value) in number_fields{
+        if let Some(value) = value{
+            insert(&mut tags, name, &value.to_string());
+        }
+    }
+    Some(tags)
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> Option<u32>{
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32>{
+    Some(u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn invalid_data(message: &str) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// vendor length, vendor, number of comments, then length and NAME=value for each comment, all little endian
+fn parse_vorbis_comments(bytes: &[u8]) -> AudioTags{
+    let mut tags = AudioTags::new();
+    let Some(vendor_length) = read_u32_le(bytes, 0) else {
+        return tags;
+    };
+    let mut offset = 4 + vendor_length as usize;
+    let Some(count) = read_u32_le(bytes, offset) else {
+        return tags;
+    };
+    offset += 4;
+    for _ in 0..count{
+        let Some(length) = read_u32_le(bytes, offset) else {
+            break;
+        };
+        offset += 4;
+        let Some(comment) = bytes.get(offset..offset + length as usize) else {
+            break;
+        };
+        offset += length as usize;
+        let comment = String::from_utf8_lossy(comment);
+        let Some((name, value)) = comment.split_once('=') else {
+            continue;
+        };
+        let name = match name.to_lowercase().as_str(){
+            "tracknumber" => "track".to_string(),
+            "discnumber" => "disc".to_string(),
+            "date" => "year".to_string(),
+            "album artist" | "album_artist" => "albumartist".to_string(),
+            name => name.to_string(),
+        };
+        insert(&mut tags, &name, value);
+    }
+    normalize_common_fields(&mut tags);
+    tags
+}
+
+// fLaC, then metadata blocks: last block flag and type in one byte, 24 bit length; the comments are block type 4.
+fn read_flac_comments(file: &mut fs::File) -> io::Result<AudioTags>{
+    file.seek(SeekFrom::Start(4))?;
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        if header[0] & 0x7f == 4{
+            let mut block = vec![];
+            file.by_ref().take(length).read_to_end(&mut block)?;
+            return Ok(parse_vorbis_comments(&block));
+        }
+        if is_last{
+            return Ok(AudioTags::new());
+        }
+        file.seek(SeekFrom::Current(length as i64))?;
+    }
+}
+
+// The comments are the second packet of the first stream, after "\x03vorbis" or "OpusTags".
+fn read_ogg_comments(file: &mut fs::File) -> io::Result<AudioTags>{
+    let mut reader = io::BufReader::new(file);
+    let mut serial = None;
+    let mut packets: Vec<Vec<u8>> = vec![vec![]];
+    while packets.len() < 3{
+        let mut header = [0u8; 27];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"OggS"{
+            return Err(invalid_data("not an Ogg page"));
+        }
+        let page_serial = read_u32_le(&header, 14);
+        let mut segment_table = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut segment_table)?;
+        let page_length: u64 = segment_table.iter().map(|&length| length as u64).sum();
+        if *serial.get_or_insert(page_serial) != page_serial{
+            io::copy(&mut reader.by_ref().take(page_length), &mut io::sink())?;
+            continue;
+        }
+        for length in segment_table{
+            let packet = packets.last_mut().unwrap();
+            reader.by_ref().take(length as u64).read_to_end(packet)?;
+            if packet.len() > MAX_OGG_COMMENT_SIZE{
+                return Err(invalid_data("Ogg packet too big"));
+            }
+            // a segment shorter than 255 bytes ends the packet
+            if length < 255{
+                packets.push(vec![]);
+            }
+        }
+    }
+    let comments = &packets[1];
+    if let Some(comments) = comments.strip_prefix(b"\x03vorbis").or(comments.strip_prefix(b"OpusTags")){
+        return Ok(parse_vorbis_comments(comments));
+    }
+    Ok(AudioTags::new())
+}
+
+// Atoms are a 32 bit big endian size (1: a 64 bit size follows, 0: up to the end) and a 4 byte type.
+// Returns the type and the content of each atom.
+fn parse_atoms(bytes: &[u8]) -> Vec<([u8; 4], &[u8])>{
+    let mut atoms = vec![];
+    let mut offset = 0;
+    while offset + 8 <= bytes.len(){
+        let mut size = read_u32_be(bytes, offset).unwrap_or(0) as usize;
+        let kind: [u8; 4] = bytes[offset + 4..offset + 8].try_into().unwrap();
+        let mut header_size = 8;
+        if size == 1{
+            let Some(large_size) = bytes.get(offset + 8..offset + 16) else {
+                break;
+            };
+            // a size that does not fit in memory cannot be the size of an atom of this buffer
+            let Ok(large_size) = usize::try_from(u64::from_be_bytes(large_size.try_into().unwrap())) else {
+                break;
+            };
+            size = large_size;
+            header_size = 16;
+        }
+        else if size == 0{
+            size = bytes.len() - offset;
+        }
+        let Some(end) = offset.checked_add(size) else {
+            break;
+        };
+        if size < header_size || end > bytes.len(){
+            break;
+        }
+        atoms.push((kind, &bytes[offset + header_size..end]));
+        offset = end;
+    }
+    atoms
+}
+
+fn find_atom<'a>(bytes: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]>{
+    parse_atoms(bytes).into_iter().find(|(atom_kind, _)| atom_kind == kind).map(|(_, content)| content)
+}
+
+// The tags are in moov/udta/meta/ilst, each item holds a data atom with a type, a locale and the value.
+fn read_mp4_atoms(file: &mut fs::File) -> io::Result<AudioTags>{
+    let mut tags = AudioTags::new();
+    // only the moov atom is read into memory, the media data is skipped
+    let moov = loop {
+        let mut header = [0u8; 8];
+        if file.read_exact(&mut header).is_err(){
+            return Ok(tags);
+        }
+        let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let content_size = match size{
+            0 => None,
+            1 => {
+                let mut large_size = [0u8; 8];
+                file.read_exact(&mut large_size)?;
+                Some(u64::from_be_bytes(large_size).checked_sub(16).ok_or(invalid_data("invalid atom size"))?)
+            },
+            _ => Some(size.checked_sub(8).ok_or(invalid_data("invalid atom size"))?),
+        };
+        if &header[4..8] == b"moov"{
+            let mut moov = vec![];
+            match content_size{
+                Some(content_size) => file.by_ref().take(content_size).read_to_end(&mut moov)?,
+                None => file.read_to_end(&mut moov)?,
+            };
+            break moov;
+        }
+        match content_size{
+            Some(content_size) => file.seek(SeekFrom::Current(i64::try_from(content_size).map_err(|_| invalid_data("invalid atom size"))?))?,
+            None => return Ok(tags),
+        };
+    };
+    let ilst = find_atom(&moov, b"udta")
+        .and_then(|udta| find_atom(udta, b"meta"))
+        // meta has a version and flags before its children
+        .and_then(|meta| meta.get(4..))
+        .and_then(|meta| find_atom(meta, b"ilst"));
+    let Some(ilst) = ilst else {
+        return Ok(tags);
+    };
+    for (kind, item) in parse_atoms(ilst){
+        let Some(data) = find_atom(item, b"data").and_then(|data| data.get(8..)) else {
+            continue;
+        };
+        let name = match &kind{
+            b"\xa9nam" => "title",
+            b"\xa9ART" => "artist",
+            b"\xa9alb" => "album",
+            b"aART" => "albumartist",
+            b"\xa9day" => "year",
+            b"\xa9gen" => "genre",
+            b"\xa9wrt" => "composer",
+            // binary: 2 bytes padding, the number, the total
+            b"trkn" | b"disk" => {
+                if let Some(number) = data.get(2..4){
+                    let number = u16::from_be_bytes([number[0], number[1]]);
+                    if number > 0{
+                        insert(&mut tags, if &kind == b"trkn" { "track" } else { "disc" }, &number.to_string());
+                    }
+                }
+                continue;
+            },
+            _ => continue,
+        };
+        insert(&mut tags, name, &String::from_utf8_lossy(data));
+    }
+    normalize_common_fields(&mut tags);
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    fn vorbis_comments(comments: &[&str]) -> Vec<u8>{
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&6u32.to_le_bytes());
+        bytes.extend_from_slice(b"vendor");
+        bytes.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments{
+            bytes.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(comment.as_bytes());
+        }
+        bytes
+    }
+
+    fn atom(kind: &[u8], content: &[u8]) -> Vec<u8>{
+        let mut bytes = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    fn data_atom(data_type: u32, value: &[u8]) -> Vec<u8>{
+        let mut content = data_type.to_be_bytes().to_vec();
+        content.extend_from_slice(&[0, 0, 0, 0]);
+        content.extend_from_slice(value);
+        atom(b"data", &content)
+    }
+
+    fn expected(fields: &[(&str, &str)]) -> AudioTags{
+        fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_read_flac_comments() {
+        let directory = test_directory("audio-flac");
+        let comments = vorbis_comments(&["TITLE=Let It Be", "Artist=The Beatles", "TRACKNUMBER=6/12", "DATE=1970-05-08", "BROKEN"]);
+        let mut flac = b"fLaC".to_vec();
+        // a STREAMINFO block before the comments
+        flac.extend_from_slice(&[0, 0, 0, 34]);
+        flac.extend_from_slice(&[0; 34]);
+        flac.push(0x84);
+        flac.extend_from_slice(&(comments.len() as u32).to_be_bytes()[1..]);
+        flac.extend_from_slice(&comments);
+        fs::write(directory.join("song.flac"), flac).unwrap();
+        assert_eq!(
+            read_tags(&directory.join("song.flac")),
+            Some(expected(&[("title", "Let It Be"), ("artist", "The Beatles"), ("track", "6"), ("year", "1970")]))
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_ogg_comments() {
+        let directory = test_directory("audio-ogg");
+        let page = |packets: &[&[u8]]| {
+            let mut segment_table = vec![];
+            let mut content = vec![];
+            for packet in packets{
+                segment_table.extend(std::iter::repeat_n(255u8, packet.len() / 255));
+                segment_table.push((packet.len() % 255) as u8);
+                content.extend_from_slice(packet);
+            }
+            let mut page = b"OggS".to_vec();
+            page.extend_from_slice(&[0; 10]);
+            page.extend_from_slice(&7u32.to_le_bytes());
+            page.extend_from_slice(&[0; 8]);
+            page.push(segment_table.len() as u8);
+            page.extend_from_slice(&segment_table);
+            page.extend_from_slice(&content);
+            page
+        };
+        let mut comments = b"\x03vorbis".to_vec();
+        comments.extend_from_slice(&vorbis_comments(&["ALBUM=Abbey Road", &format!("COMMENT={}", "x".repeat(300))]));
+        let mut ogg = page(&[b"\x01vorbis identification"]);
+        ogg.extend_from_slice(&page(&[&comments, b"\x05vorbis setup"]));
+        fs::write(directory.join("song.ogg"), ogg).unwrap();
+        let tags = read_tags(&directory.join("song.ogg")).unwrap();
+        assert_eq!(tags.get("album"), Some(&"Abbey Road".to_string()));
+        assert_eq!(tags.get("comment").map(String::len), Some(300), "Packets can span several segments.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_mp4_atoms() {
+        let directory = test_directory("audio-mp4");
+        let ilst = [
+            atom(b"\xa9nam", &data_atom(1, b"Here Comes the Sun")),
+            atom(b"\xa9ART", &data_atom(1, b"The Beatles")),
+            atom(b"trkn", &data_atom(0, &[0, 0, 0, 7, 0, 17, 0, 0])),
+            atom(b"\xa9day", &data_atom(1, b"1969-09-26T00:00:00Z")),
+        ].concat();
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend_from_slice(&atom(b"hdlr", &[0; 25]));
+        meta.extend_from_slice(&atom(b"ilst", &ilst));
+        let moov = atom(b"moov", &[atom(b"mvhd", &[0; 100]), atom(b"udta", &atom(b"meta", &meta))].concat());
+        let m4a = [atom(b"ftyp", b"M4A \0\0\0\0"), atom(b"mdat", &[0; 64]), moov].concat();
+        fs::write(directory.join("song.m4a"), m4a).unwrap();
+        assert_eq!(
+            read_tags(&directory.join("song.m4a")),
+            Some(expected(&[("title", "Here Comes the Sun"), ("artist", "The Beatles"), ("track", "7"), ("year", "1969")]))
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_parse_atoms_with_invalid_sizes() {
+        let free = atom(b"free", &[0; 4]);
+        let mut huge = free.clone();
+        huge.extend_from_slice(&[0, 0, 0, 1]);
+        huge.extend_from_slice(b"junk");
+        huge.extend_from_slice(&0xFFFF_FFFF_FFFF_FFFCu64.to_be_bytes());
+        huge.extend_from_slice(&[0; 8]);
+        assert_eq!(parse_atoms(&huge), vec![(*b"free", &[0u8; 4][..])], "Parsing stops at an atom bigger than the buffer.");
+        let mut truncated = free.clone();
+        truncated.extend_from_slice(&atom(b"udta", &[0; 32])[..20]);
+        assert_eq!(parse_atoms(&truncated).len(), 1, "Parsing stops at a truncated atom.");
+
+        let directory = test_directory("audio-mp4-invalid");
+        let m4a = [atom(b"ftyp", b"M4A \0\0\0\0"), huge].concat();
+        fs::write(directory.join("song.m4a"), m4a).unwrap();
+        assert_eq!(read_tags(&directory.join("song.m4a")), None, "A corrupt file has no tags.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_id3() {
+        let directory = test_directory("audio-id3");
+        let mut tag = id3::Tag::new();
+        tag.set_title("Yesterday");
+        tag.set_artist("The Beatles");
+        tag.set_track(13);
+        tag.set_year(1965);
+        let mut mp3 = vec![];
+        tag.write_to(&mut mp3, id3::Version::Id3v24).unwrap();
+        mp3.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        fs::write(directory.join("song.mp3"), mp3).unwrap();
+        assert_eq!(
+            read_tags(&directory.join("song.mp3")),
+            Some(expected(&[("title", "Yesterday"), ("artist", "The Beatles"), ("track", "13"), ("year", "1965")]))
+        );
+        fs::write(directory.join("untagged.mp3"), [0xff, 0xfb, 0x90, 0x00, 0, 0, 0, 0]).unwrap();
+        assert_eq!(read_tags(&directory.join("untagged.mp3")), None);
+        fs::write(directory.join("notes.txt"), "not audio").unwrap();
+        assert_eq!(read_tags(&directory.join("notes.txt")), None);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
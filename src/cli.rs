@@ -1,14 +1,102 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::filters::Normalization;
+use crate::metadata::{self, FileType};
+use crate::planner::{ConflictPolicy, SUFFIX_NUMBER_PLACEHOLDER};
+
+// Exit codes of the program besides 0 for success, documented in the help.
+// nothing could be renamed, or an atomic run was rolled back
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE_ERROR: i32 = 2;
+pub const EXIT_NOTHING_MATCHED: i32 = 3;
+// some files were renamed and some were not
+pub const EXIT_PARTIAL_FAILURE: i32 = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    UnknownParameter(String),
+    DuplicateParameter(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    MissingParameter(String),
+    DirectoryNotFound(PathBuf),
+    UnpairedNewNames(usize, usize),
+    IncompatibleParameters(String, String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownParameter(parameter) => write!(f, "Unknown parameter '{}'.", parameter),
+            CliError::DuplicateParameter(parameter) => write!(f, "The parameter '{}' is given more than once.", parameter),
+            CliError::MissingValue(parameter) => write!(f, "The parameter '{}' requires a value.", parameter),
+            CliError::InvalidValue(parameter, reason) => write!(f, "The value of '{}' {}.", parameter, reason),
+            CliError::MissingParameter(parameter) => write!(f, "The parameter '{}' is required.", parameter),
+            CliError::DirectoryNotFound(directory) => write!(f, "The directory '{}' does not exist.", directory.display()),
+            CliError::UnpairedNewNames(filters, outputs) => write!(f, "Give one new name for all filters or one per filter, {} filters and {} new names were given.", filters, outputs),
+            CliError::IncompatibleParameters(parameter, other) => write!(f, "'{}' cannot be used together with '{}'.", parameter, other),
+        }
+    }
+}
+
+// Which directory entries are matched by the filters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenameTarget {
+    Files,
+    Dirs,
+    All,
+}
+
+impl RenameTarget {
+    pub fn includes(&self, is_dir: bool) -> bool {
+        match self {
+            RenameTarget::Files => !is_dir,
+            RenameTarget::Dirs => is_dir,
+            RenameTarget::All => true,
+        }
+    }
+}
+
 pub struct CliParameters {
     pub Help: bool,
     pub Version: bool,
     pub Verbose: bool,
     pub DryRun: bool,
     pub Recursive: bool,
-    pub Directory: std::path::PathBuf,
-    pub Filter: String,
-    pub Output: String,
+    pub Directory: PathBuf,
+    // include filters, a file is renamed when any of them matches
+    pub Filters: Vec<String>,
+    // one new name shared by all filters or one per filter, in the same order
+    pub Outputs: Vec<String>,
+    pub Excludes: Vec<String>,
     pub WildcardChar: char,
     pub PositionSelectWrapper: (char, char),
+    pub Regex: bool,
+    pub Undo: bool,
+    pub UndoRunId: Option<String>,
+    pub Atomic: bool,
+    pub OnConflict: ConflictPolicy,
+    pub SuffixPattern: String,
+    pub Sort: Option<String>,
+    pub Normalize: Option<Normalization>,
+    pub IgnoreCase: bool,
+    pub Rules: Option<PathBuf>,
+    pub MinSize: Option<u64>,
+    pub MaxSize: Option<u64>,
+    pub ModifiedAfter: Option<SystemTime>,
+    pub ModifiedBefore: Option<SystemTime>,
+    pub Type: Option<FileType>,
+    pub NewerThan: Option<PathBuf>,
+    pub Target: RenameTarget,
+    // new names can contain directories, they are created unless --no-create-dirs is given
+    pub CreateDirs: bool,
+    pub RemoveEmptyDirs: bool,
+    // time tokens are formatted in UTC instead of local time
+    pub Utc: bool,
+    // groups the matched files by content, duplicates are only renamed when a new name is given
+    pub FindDuplicates: bool,
 }
 
 enum ParametersType {
@@ -20,13 +108,34 @@ enum ParametersType {
     Directory,
     Filter,
     Output,
+    Exclude,
     WildcardChar,
     PositionSelectWrapper,
+    Regex,
+    Undo,
+    Atomic,
+    OnConflict,
+    SuffixPattern,
+    Sort,
+    Normalize,
+    IgnoreCase,
+    Rules,
+    MinSize,
+    MaxSize,
+    ModifiedAfter,
+    ModifiedBefore,
+    Type,
+    NewerThan,
+    Target,
+    NoCreateDirs,
+    RemoveEmptyDirs,
+    Utc,
+    FindDuplicates,
     Error,
 }
 
-fn map_parameter_to_type(parameter: &String) -> ParametersType {
-    match parameter.as_str() {
+fn map_parameter_to_type(parameter: &str) -> ParametersType {
+    match parameter {
         "--help" => ParametersType::Help,
         "-h" => ParametersType::Help,
         "--version" => ParametersType::Version,
@@ -42,27 +151,40 @@ fn map_parameter_to_type(parameter: &String) -> ParametersType {
         "-f" => ParametersType::Filter,
         "--new-name" => ParametersType::Output,
         "-n" => ParametersType::Output,
+        "--exclude" => ParametersType::Exclude,
+        "-e" => ParametersType::Exclude,
         "--wildcard-char" => ParametersType::WildcardChar,
         "--position-select-wrapper" => ParametersType::PositionSelectWrapper,
+        "--regex" => ParametersType::Regex,
+        "--undo" => ParametersType::Undo,
+        "--atomic" => ParametersType::Atomic,
+        "--on-conflict" => ParametersType::OnConflict,
+        "--suffix-pattern" => ParametersType::SuffixPattern,
+        "--sort" => ParametersType::Sort,
+        "--normalize" => ParametersType::Normalize,
+        "--ignore-case" => ParametersType::IgnoreCase,
+        "-i" => ParametersType::IgnoreCase,
+        "--rules" => ParametersType::Rules,
+        "--min-size" => ParametersType::MinSize,
+        "--max-size" => ParametersType::MaxSize,
+        "--modified-after" => ParametersType::ModifiedAfter,
+        "--modified-before" => ParametersType::ModifiedBefore,
+        "--type" => ParametersType::Type,
+        "--newer-than" => ParametersType::NewerThan,
+        // --dirs is a shorthand for --target dirs
+        "--target" => ParametersType::Target,
+        "--dirs" => ParametersType::Target,
+        "--no-create-dirs" => ParametersType::NoCreateDirs,
+        "--remove-empty-dirs" => ParametersType::RemoveEmptyDirs,
+        "--utc" => ParametersType::Utc,
+        "--find-duplicates" => ParametersType::FindDuplicates,
         _ => ParametersType::Error,
     }
 }
 
 impl PartialEq for ParametersType{
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (ParametersType::Help, ParametersType::Help) => true,
-            (ParametersType::Version, ParametersType::Version) => true,
-            (ParametersType::Vebose, ParametersType::Vebose) => true,
-            (ParametersType::DryRun, ParametersType::DryRun) => true,
-            (ParametersType::Recursive, ParametersType::Recursive) => true,
-            (ParametersType::Directory, ParametersType::Directory) => true,
-            (ParametersType::Filter, ParametersType::Filter) => true,
-            (ParametersType::Output, ParametersType::Output) => true,
-            (ParametersType::WildcardChar, ParametersType::WildcardChar) => true,
-            (ParametersType::PositionSelectWrapper, ParametersType::PositionSelectWrapper) => true,
-            _ => false,
-        }
+        core::mem::discriminant(self) == core::mem::discriminant(other)
     }
 }
 
@@ -72,31 +194,75 @@ impl std::hash::Hash for ParametersType {
     }
 }
 
+impl ParametersType {
+    fn is_repeatable(&self) -> bool {
+        matches!(self, ParametersType::Filter | ParametersType::Output | ParametersType::Exclude)
+    }
+}
+
 impl Eq for ParametersType {
     
 }
 
+// Long parameters can also be written as --name=value.
+fn split_assignments(args: Vec<String>) -> Vec<String> {
+    let mut split_args = vec![];
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                split_args.push(name.to_string());
+                split_args.push(value.to_string());
+            },
+            _ => split_args.push(arg),
+        }
+    }
+    split_args
+}
+
 impl CliParameters {
-    pub fn new(args: Vec<String>) -> Self {
+    pub fn new(args: Vec<String>) -> Result<Self, CliError> {
         use ParametersType::*;
+        let args = split_assignments(args);
         let mut default =CliParameters {
             Help: false,
             Version: false,
             Verbose: false,
             DryRun: false,
             Recursive: false,
-            Directory: std::path::PathBuf::new(),
-            Filter: "".to_string(),
-            Output: "".to_string(),
+            Directory: PathBuf::from("."),
+            Filters: vec![],
+            Outputs: vec![],
+            Excludes: vec![],
             WildcardChar: '*',
             PositionSelectWrapper: ('(', ')'),
+            Regex: false,
+            Undo: false,
+            UndoRunId: None,
+            Atomic: false,
+            OnConflict: ConflictPolicy::Error,
+            SuffixPattern: " (#)".to_string(),
+            Sort: None,
+            Normalize: None,
+            IgnoreCase: false,
+            Rules: None,
+            MinSize: None,
+            MaxSize: None,
+            ModifiedAfter: None,
+            ModifiedBefore: None,
+            Type: None,
+            NewerThan: None,
+            Target: RenameTarget::Files,
+            CreateDirs: true,
+            RemoveEmptyDirs: false,
+            Utc: false,
+            FindDuplicates: false,
         };
         let mut defaults_overriden :std::collections::HashSet<ParametersType> = std::collections::HashSet::new();
         let mut index = 1;
         while index < args.len(){
             let parameter_type = map_parameter_to_type(&args[index]);
-            if defaults_overriden.contains(&parameter_type) {
-                panic!("Duplicate parameter: {}", args[index]);
+            if defaults_overriden.contains(&parameter_type) && !parameter_type.is_repeatable() {
+                return Err(CliError::DuplicateParameter(args[index].clone()));
             }
             match &parameter_type{
                 Help => default.Help = true,
@@ -110,75 +276,244 @@ impl CliParameters {
                 Directory => {
                     index += 1;
                     if index < args.len() {
-                        default.Directory = std::path::PathBuf::from(&args[index]);
+                        default.Directory = PathBuf::from(&args[index]);
                         if !default.Directory.exists(){
-                            panic!("Directory does not exist.");
+                            return Err(CliError::DirectoryNotFound(default.Directory));
                         }
                     }
                     else {
-                        panic!("Directory parameter requires a value.");
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
                     }
                 },
                 Filter => {
                     index +=1;
                     if index < args.len() {
-                        default.Filter = args[index].clone();
+                        default.Filters.push(args[index].clone());
                     }
                     else {
-                        panic!("Filter parameter requires a value.");
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
                     }
                 },
                 Output => {
                     index += 1;
                     if index < args.len() {
-                        default.Output = args[index].clone();
+                        default.Outputs.push(args[index].clone());
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Exclude => {
+                    index += 1;
+                    if index < args.len() {
+                        default.Excludes.push(args[index].clone());
                     }
                     else {
-                        panic!("Output parameter requires a value.");
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
                     }
                 },
                 WildcardChar => {
                     index += 1;
                     if index < args.len() {
                         let wildcard_char = &args[index];
-                        if wildcard_char.len() == 1 {
+                        if wildcard_char.chars().count() == 1 {
                             default.WildcardChar = wildcard_char.chars().next().unwrap();
                         }
                         else {
-                            panic!("WildcardChar parameter must be a single character.");
+                            return Err(CliError::InvalidValue(args[index - 1].clone(), "must be a single character".to_string()));
                         }
                     }
                     else {
-                        panic!("WildcardChar parameter requires a value.");
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
                     }
                 },
                 PositionSelectWrapper => {
                     index += 1;
                     if index < args.len() {
                         let position_select_wrapper = &args[index];
-                        if position_select_wrapper.len() == 2 {
+                        if position_select_wrapper.chars().count() == 2 {
                             default.PositionSelectWrapper = (
                                 position_select_wrapper.chars().nth(0).unwrap(),
                                 position_select_wrapper.chars().nth(1).unwrap(),
                             );
                         }
                         else {
-                            panic!("PositionSelectWrapper parameter must be two characters.");
+                            return Err(CliError::InvalidValue(args[index - 1].clone(), "must be two characters".to_string()));
+                        }
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Regex => default.Regex = true,
+                Undo => {
+                    default.Undo = true;
+                    // the run id is optional, the last run is undone without it
+                    if index + 1 < args.len() && !args[index + 1].starts_with('-') {
+                        index += 1;
+                        default.UndoRunId = Some(args[index].clone());
+                    }
+                },
+                Atomic => default.Atomic = true,
+                OnConflict => {
+                    index += 1;
+                    if index < args.len() {
+                        default.OnConflict = match args[index].as_str() {
+                            "skip" => ConflictPolicy::Skip,
+                            "error" => ConflictPolicy::Error,
+                            "overwrite" => ConflictPolicy::Overwrite,
+                            "suffix" => ConflictPolicy::Suffix,
+                            _ => return Err(CliError::InvalidValue(args[index - 1].clone(), "must be one of skip, error, overwrite or suffix".to_string())),
+                        };
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                SuffixPattern => {
+                    index += 1;
+                    if index < args.len() {
+                        let suffix_pattern = &args[index];
+                        if !suffix_pattern.contains(SUFFIX_NUMBER_PLACEHOLDER) || suffix_pattern.contains(std::path::is_separator) {
+                            return Err(CliError::InvalidValue(args[index - 1].clone(), format!("must contain {} and no path separator", SUFFIX_NUMBER_PLACEHOLDER)));
                         }
+                        default.SuffixPattern = suffix_pattern.clone();
                     }
                     else {
-                        panic!("PositionSelectWrapper parameter requires a value.");
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Sort => {
+                    index += 1;
+                    if index < args.len() {
+                        default.Sort = Some(args[index].clone());
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Normalize => {
+                    index += 1;
+                    if index < args.len() {
+                        default.Normalize = match args[index].to_lowercase().as_str() {
+                            "nfc" => Some(Normalization::Nfc),
+                            "nfd" => Some(Normalization::Nfd),
+                            _ => return Err(CliError::InvalidValue(args[index - 1].clone(), "must be nfc or nfd".to_string())),
+                        };
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                IgnoreCase => default.IgnoreCase = true,
+                Rules => {
+                    index += 1;
+                    if index < args.len() {
+                        default.Rules = Some(PathBuf::from(&args[index]));
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                MinSize | MaxSize => {
+                    index += 1;
+                    if index < args.len() {
+                        let size = metadata::parse_size(&args[index]).map_err(|reason| CliError::InvalidValue(args[index - 1].clone(), reason))?;
+                        match parameter_type {
+                            MinSize => default.MinSize = Some(size),
+                            _ => default.MaxSize = Some(size),
+                        }
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                ModifiedAfter | ModifiedBefore => {
+                    index += 1;
+                    if index < args.len() {
+                        let time = metadata::parse_time(&args[index], SystemTime::now()).map_err(|reason| CliError::InvalidValue(args[index - 1].clone(), reason))?;
+                        match parameter_type {
+                            ModifiedAfter => default.ModifiedAfter = Some(time),
+                            _ => default.ModifiedBefore = Some(time),
+                        }
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Type => {
+                    index += 1;
+                    if index < args.len() {
+                        default.Type = match FileType::parse(&args[index]) {
+                            Some(file_type) => Some(file_type),
+                            None => return Err(CliError::InvalidValue(args[index - 1].clone(), "must be file, dir or symlink".to_string())),
+                        };
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                NewerThan => {
+                    index += 1;
+                    if index < args.len() {
+                        default.NewerThan = Some(PathBuf::from(&args[index]));
+                    }
+                    else {
+                        return Err(CliError::MissingValue(args[index - 1].clone()));
+                    }
+                },
+                Target => {
+                    if args[index] == "--dirs" {
+                        default.Target = RenameTarget::Dirs;
+                    }
+                    else {
+                        index += 1;
+                        if index < args.len() {
+                            default.Target = match args[index].as_str() {
+                                "files" => RenameTarget::Files,
+                                "dirs" => RenameTarget::Dirs,
+                                "all" => RenameTarget::All,
+                                _ => return Err(CliError::InvalidValue(args[index - 1].clone(), "must be one of files, dirs or all".to_string())),
+                            };
+                        }
+                        else {
+                            return Err(CliError::MissingValue(args[index - 1].clone()));
+                        }
                     }
                 },
+                NoCreateDirs => default.CreateDirs = false,
+                RemoveEmptyDirs => default.RemoveEmptyDirs = true,
+                Utc => default.Utc = true,
+                FindDuplicates => default.FindDuplicates = true,
                 Error => {
-                    panic!("Invalid parameter: {}", args[index]);
+                    return Err(CliError::UnknownParameter(args[index].clone()));
                 },
             }
             defaults_overriden.insert(parameter_type);
             
             index += 1;
         }
-        default   
+        if default.Outputs.len() > 1 && default.Outputs.len() != default.Filters.len() {
+            return Err(CliError::UnpairedNewNames(default.Filters.len(), default.Outputs.len()));
+        }
+        // only directories can be of type dir, so --type dir renames directories unless another target was chosen
+        if default.Type == Some(FileType::Dir) {
+            if !defaults_overriden.contains(&ParametersType::Target) {
+                default.Target = RenameTarget::Dirs;
+            }
+            else if default.Target == RenameTarget::Files {
+                return Err(CliError::IncompatibleParameters("--type dir".to_string(), "--target files".to_string()));
+            }
+        }
+        // a file replaced by the overwrite policy cannot be brought back by the rollback
+        if default.Atomic && default.OnConflict == ConflictPolicy::Overwrite {
+            return Err(CliError::IncompatibleParameters("--on-conflict overwrite".to_string(), "--atomic".to_string()));
+        }
+        // duplicates are renamed, never allowed to replace another file
+        if default.FindDuplicates && default.OnConflict == ConflictPolicy::Overwrite {
+            return Err(CliError::IncompatibleParameters("--on-conflict overwrite".to_string(), "--find-duplicates".to_string()));
+        }
+        Ok(default)
     }
 }
 
@@ -187,33 +522,32 @@ impl CliParameters {
 mod tests {
     use super::*;
     use std::env;
-    use std::path::PathBuf;
 
     #[test]
     fn test_help_parameter() {
         let args = vec!["program".to_string(), "--help".to_string()];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.Help, "Help flag should be set to true.");
     }
 
     #[test]
     fn test_version_parameter() {
         let args = vec!["program".to_string(), "--version".to_string()];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.Version, "Version flag should be set to true.");
     }
 
     #[test]
     fn test_dry_run_parameter() {
         let args = vec!["program".to_string(), "--dry-run".to_string()];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.DryRun, "DryRun flag should be set to true.");
     }
 
     #[test]
     fn test_recursive_parameter() {
         let args = vec!["program".to_string(), "--recursive".to_string()];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.Recursive, "Recursive flag should be set to true.");
     }
 
@@ -225,7 +559,7 @@ mod tests {
             "--directory".to_string(),
             current_dir.to_str().unwrap().to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert_eq!(
             params.Directory,
             current_dir,
@@ -240,9 +574,9 @@ mod tests {
             "--filter".to_string(),
             "file_*".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert_eq!(
-            params.Filter, "file_*",
+            params.Filters, vec!["file_*"],
             "Filter should be set to 'file_*'."
         );
     }
@@ -251,12 +585,12 @@ mod tests {
     fn test_output_parameter() {
         let args = vec![
             "program".to_string(),
-            "--output".to_string(),
+            "--new-name".to_string(),
             "output_file".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert_eq!(
-            params.Output, "output_file",
+            params.Outputs, vec!["output_file"],
             "Output should be set to 'output_file'."
         );
     }
@@ -268,7 +602,7 @@ mod tests {
             "--wildcard-char".to_string(),
             "?".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert_eq!(
             params.WildcardChar, '?',
             "WildcardChar should be set to '?'."
@@ -282,7 +616,7 @@ mod tests {
             "--position-select-wrapper".to_string(),
             "[]".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert_eq!(
             params.PositionSelectWrapper,
             ('[', ']'),
@@ -290,6 +624,198 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_regex_parameter() {
+        let args = vec!["program".to_string(), "--regex".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.Regex, "Regex flag should be set to true.");
+    }
+
+    #[test]
+    fn test_undo_parameter() {
+        let args = vec!["program".to_string(), "--undo".to_string(), "--dry-run".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.Undo, "Undo flag should be set to true.");
+        assert_eq!(params.UndoRunId, None, "Without a run id the last run is undone.");
+        assert!(params.DryRun, "The next parameter is not taken as the run id.");
+
+        let args = vec!["program".to_string(), "--undo".to_string(), "1700000000-42".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.Undo, "Undo flag should be set to true.");
+        assert_eq!(params.UndoRunId, Some("1700000000-42".to_string()), "UndoRunId should be set to the given run.");
+    }
+
+    #[test]
+    fn test_atomic_parameter() {
+        let args = vec!["program".to_string(), "--atomic".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.Atomic, "Atomic flag should be set to true.");
+    }
+
+    #[test]
+    fn test_on_conflict_parameter() {
+        let args = vec!["program".to_string(), "--on-conflict".to_string(), "suffix".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.OnConflict, ConflictPolicy::Suffix, "OnConflict should be set to suffix.");
+
+        let args = vec!["program".to_string(), "--on-conflict=skip".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.OnConflict, ConflictPolicy::Skip, "The value can be given after '='.");
+    }
+
+    #[test]
+    fn test_invalid_on_conflict() {
+        let args = vec!["program".to_string(), "--on-conflict".to_string(), "rename".to_string()];
+        let result = CliParameters::new(args);
+        assert!(result.is_err(), "Unknown conflict policies should cause an error.");
+    }
+
+    #[test]
+    fn test_overwrite_refused_with_atomic() {
+        let args = vec!["program".to_string(), "--atomic".to_string(), "--on-conflict".to_string(), "overwrite".to_string()];
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::IncompatibleParameters("--on-conflict overwrite".to_string(), "--atomic".to_string())), "An overwritten file could not be restored by the rollback.");
+    }
+
+    #[test]
+    fn test_suffix_pattern_parameter() {
+        let args = vec!["program".to_string(), "--suffix-pattern".to_string(), "_#".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.SuffixPattern, "_#", "SuffixPattern should be set to '_#'.");
+
+        let args = vec!["program".to_string(), "--suffix-pattern".to_string(), "_copy".to_string()];
+        let result = CliParameters::new(args);
+        assert!(result.is_err(), "A suffix pattern without # should cause an error.");
+    }
+
+    #[test]
+    fn test_sort_parameter() {
+        let args = vec!["program".to_string(), "--sort".to_string(), "mtime,name".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Sort, Some("mtime,name".to_string()), "Sort should be set to 'mtime,name'.");
+    }
+
+    #[test]
+    fn test_normalize_parameter() {
+        let args = vec!["program".to_string(), "--normalize".to_string(), "NFD".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Normalize, Some(Normalization::Nfd), "Normalize should be set to NFD.");
+
+        let args = vec!["program".to_string(), "--normalize".to_string(), "nfkc".to_string()];
+        let result = CliParameters::new(args);
+        assert!(result.is_err(), "Unsupported normalization forms should cause an error.");
+    }
+
+    #[test]
+    fn test_ignore_case_parameter() {
+        let args = vec!["program".to_string(), "-i".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.IgnoreCase, "IgnoreCase flag should be set to true.");
+    }
+
+    #[test]
+    fn test_rules_parameter() {
+        let args = vec!["program".to_string(), "--rules".to_string(), "ingest.toml".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Rules, Some(PathBuf::from("ingest.toml")), "Rules should be set to 'ingest.toml'.");
+    }
+
+    #[test]
+    fn test_metadata_parameters() {
+        let args = vec![
+            "program".to_string(),
+            "--min-size".to_string(), "1G".to_string(),
+            "--max-size=2G".to_string(),
+            "--modified-before".to_string(), "7d".to_string(),
+            "--modified-after".to_string(), "2024-01-31T00:00:00Z".to_string(),
+            "--type".to_string(), "symlink".to_string(),
+            "--newer-than".to_string(), "reference.txt".to_string(),
+        ];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.MinSize, Some(1 << 30), "MinSize should be set to 1 GiB.");
+        assert_eq!(params.MaxSize, Some(2 << 30), "MaxSize should be set to 2 GiB.");
+        assert!(params.ModifiedBefore.unwrap() < SystemTime::now(), "ModifiedBefore should be a week ago.");
+        assert_eq!(params.ModifiedAfter, Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_706_659_200)));
+        assert_eq!(params.Type, Some(FileType::Symlink), "Type should be set to symlink.");
+        assert_eq!(params.NewerThan, Some(PathBuf::from("reference.txt")), "NewerThan should be set to 'reference.txt'.");
+    }
+
+    #[test]
+    fn test_invalid_metadata_parameters() {
+        let args = vec!["program".to_string(), "--min-size".to_string(), "huge".to_string()];
+        let error = CliParameters::new(args).err().unwrap();
+        assert!(matches!(&error, CliError::InvalidValue(parameter, _) if parameter == "--min-size"));
+        assert_eq!(error.to_string(), "The value of '--min-size' must be a size like 500, 500K, 2MiB or 3GB.", "Every invalid value is reported the same way.");
+        let args = vec!["program".to_string(), "--modified-after".to_string(), "soon".to_string()];
+        assert!(CliParameters::new(args).is_err(), "Unknown times should cause an error.");
+        let args = vec!["program".to_string(), "--type".to_string(), "socket".to_string()];
+        assert!(CliParameters::new(args).is_err(), "Unknown types should cause an error.");
+    }
+
+    #[test]
+    fn test_type_dir_targets_directories() {
+        let args = vec!["program".to_string(), "--type".to_string(), "dir".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Target, RenameTarget::Dirs, "--type dir implies --dirs.");
+        let args = vec!["program".to_string(), "--type".to_string(), "dir".to_string(), "--target".to_string(), "all".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Target, RenameTarget::All, "An explicit target is kept.");
+        let args = vec!["program".to_string(), "--target".to_string(), "files".to_string(), "--type".to_string(), "dir".to_string()];
+        assert_eq!(CliParameters::new(args).err(), Some(CliError::IncompatibleParameters("--type dir".to_string(), "--target files".to_string())));
+    }
+
+    #[test]
+    fn test_target_parameter() {
+        let params = CliParameters::new(vec!["program".to_string()]).unwrap();
+        assert_eq!(params.Target, RenameTarget::Files, "Only files are renamed by default.");
+        let args = vec!["program".to_string(), "--dirs".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Target, RenameTarget::Dirs, "Target should be set to dirs.");
+        let args = vec!["program".to_string(), "--target=all".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Target, RenameTarget::All, "Target should be set to all.");
+        let args = vec!["program".to_string(), "--dirs".to_string(), "--target".to_string(), "files".to_string()];
+        assert!(matches!(CliParameters::new(args), Err(CliError::DuplicateParameter(_))), "--dirs and --target cannot be combined.");
+        let args = vec!["program".to_string(), "--target".to_string(), "folders".to_string()];
+        assert!(CliParameters::new(args).is_err(), "Unknown targets should cause an error.");
+    }
+
+    #[test]
+    fn test_directory_creation_parameters() {
+        let params = CliParameters::new(vec!["program".to_string()]).unwrap();
+        assert!(params.CreateDirs, "Missing directories are created by default.");
+        assert!(!params.RemoveEmptyDirs, "Emptied directories are kept by default.");
+        let args = vec!["program".to_string(), "--no-create-dirs".to_string(), "--remove-empty-dirs".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(!params.CreateDirs, "CreateDirs should be set to false.");
+        assert!(params.RemoveEmptyDirs, "RemoveEmptyDirs should be set to true.");
+    }
+
+    #[test]
+    fn test_utc_parameter() {
+        let args = vec!["program".to_string(), "--utc".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.Utc, "Utc flag should be set to true.");
+    }
+
+    #[test]
+    fn test_find_duplicates_parameter() {
+        let params = CliParameters::new(vec!["program".to_string()]).unwrap();
+        assert!(!params.FindDuplicates, "Duplicates are not searched by default.");
+        let args = vec!["program".to_string(), "--find-duplicates".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert!(params.FindDuplicates, "FindDuplicates flag should be set to true.");
+        let args = vec!["program".to_string(), "--find-duplicates".to_string(), "--on-conflict".to_string(), "overwrite".to_string()];
+        assert_eq!(CliParameters::new(args).err(), Some(CliError::IncompatibleParameters("--on-conflict overwrite".to_string(), "--find-duplicates".to_string())), "Duplicates never replace another file.");
+    }
+
+    #[test]
+    fn test_unicode_wildcard_char() {
+        let args = vec!["program".to_string(), "--wildcard-char".to_string(), "★".to_string()];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.WildcardChar, '★', "A single non ASCII character is a valid wildcard.");
+    }
+
     #[test]
     fn test_multiple_parameters() {
         let current_dir = env::current_dir().unwrap();
@@ -301,7 +827,7 @@ mod tests {
             "--filter".to_string(),
             "file_*".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.Help, "Help flag should be set to true.");
         assert_eq!(
             params.Directory,
@@ -309,7 +835,7 @@ mod tests {
             "Directory should be set to the current execution root."
         );
         assert_eq!(
-            params.Filter, "file_*",
+            params.Filters, vec!["file_*"],
             "Filter should be set to 'file_*'."
         );
     }
@@ -325,7 +851,7 @@ mod tests {
             current_dir.to_str().unwrap().to_string(),
             "--help".to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(params.Help, "Help flag should be set to true.");
         assert_eq!(
             params.Directory,
@@ -333,7 +859,7 @@ mod tests {
             "Directory should be set to the current execution root."
         );
         assert_eq!(
-            params.Filter, "file_*",
+            params.Filters, vec!["file_*"],
             "Filter should be set to 'file_*'."
         );
     }
@@ -345,8 +871,8 @@ mod tests {
             "--help".to_string(),
             "--help".to_string(),
         ];
-        let result = std::panic::catch_unwind(|| CliParameters::new(args));
-        assert!(result.is_err(), "Duplicate parameters should cause an error.");
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::DuplicateParameter("--help".to_string())), "Duplicate parameters should cause an error.");
     }
 
     #[test]
@@ -356,7 +882,7 @@ mod tests {
             "--position-select-wrapper".to_string(),
             "abc".to_string(),
         ];
-        let result = std::panic::catch_unwind(|| CliParameters::new(args));
+        let result = CliParameters::new(args);
         assert!(result.is_err(), "Invalid PositionSelectWrapper should cause an error.");
     }
 
@@ -367,7 +893,7 @@ mod tests {
             "--wildcard-char".to_string(),
             "**".to_string(),
         ];
-        let result = std::panic::catch_unwind(|| CliParameters::new(args));
+        let result = CliParameters::new(args);
         assert!(result.is_err(), "Invalid WildcardChar should cause an error.");
     }
 
@@ -379,7 +905,7 @@ mod tests {
             "--directory".to_string(),
             current_dir.to_str().unwrap().to_string(),
         ];
-        let params = CliParameters::new(args);
+        let params = CliParameters::new(args).unwrap();
         assert!(
             params.Directory.exists(),
             "Directory should exist."
@@ -389,4 +915,63 @@ mod tests {
             "Directory should be a valid directory."
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_missing_value_error() {
+        let args = vec!["program".to_string(), "--filter".to_string()];
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::MissingValue("--filter".to_string())), "A parameter without its value should cause an error.");
+    }
+
+    #[test]
+    fn test_unknown_parameter_error() {
+        let args = vec!["program".to_string(), "--colour".to_string()];
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::UnknownParameter("--colour".to_string())), "Unknown parameters should cause an error.");
+    }
+
+    #[test]
+    fn test_directory_not_found_error() {
+        let missing_dir = env::temp_dir().join(format!("file-renamer-missing-{}", std::process::id()));
+        let args = vec!["program".to_string(), "-d".to_string(), missing_dir.to_str().unwrap().to_string()];
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::DirectoryNotFound(missing_dir)), "A missing directory should cause an error.");
+    }
+
+    #[test]
+    fn test_default_directory() {
+        let params = CliParameters::new(vec!["program".to_string()]).unwrap();
+        assert_eq!(params.Directory, PathBuf::from("."), "The current directory is used without --directory.");
+    }
+
+    #[test]
+    fn test_multiple_filters_and_excludes() {
+        let args = vec![
+            "program".to_string(),
+            "-f".to_string(), "*.jpg".to_string(),
+            "-n".to_string(), "photo_*.jpg".to_string(),
+            "--filter".to_string(), "*.png".to_string(),
+            "--new-name=image_*.png".to_string(),
+            "-e".to_string(), "*_thumb.*".to_string(),
+            "--exclude".to_string(), "draft_*".to_string(),
+        ];
+        let params = CliParameters::new(args).unwrap();
+        assert_eq!(params.Filters, vec!["*.jpg", "*.png"], "Filters are kept in order.");
+        assert_eq!(params.Outputs, vec!["photo_*.jpg", "image_*.png"], "New names are kept in order.");
+        assert_eq!(params.Excludes, vec!["*_thumb.*", "draft_*"], "Excludes are kept in order.");
+    }
+
+    #[test]
+    fn test_unpaired_new_names_error() {
+        let args = vec![
+            "program".to_string(),
+            "-f".to_string(), "a*".to_string(),
+            "-f".to_string(), "b*".to_string(),
+            "-f".to_string(), "c*".to_string(),
+            "-n".to_string(), "x*".to_string(),
+            "-n".to_string(), "y*".to_string(),
+        ];
+        let result = CliParameters::new(args);
+        assert_eq!(result.err(), Some(CliError::UnpairedNewNames(3, 2)), "New names must be shared or paired with every filter.");
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::hashing::{self, HashAlgorithm};
+
+// Files with the same content, in the order they were given. The first one is the original, the others its duplicates.
+#[derive(Debug, PartialEq)]
+pub struct DuplicateGroup{
+    pub Size: u64,
+    pub Files: Vec<PathBuf>,
+}
+
+// Groups the regular files of the list by size, then hashes only the files that share their size with another one.
+// Empty files and symbolic links are left out. Files that cannot be read are returned with their error.
+pub fn find_duplicates(files: &[PathBuf]) -> (Vec<DuplicateGroup>, Vec<(PathBuf, io::Error)>){
+    let mut errors = vec![];
+    let mut sizes: Vec<u64> = vec![];
+    let mut files_by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for file_path in files{
+        match fs::symlink_metadata(file_path){
+            Ok(metadata) if metadata.is_file() && metadata.len() > 0 => {
+                let same_size = files_by_size.entry(metadata.len()).or_default();
+                if same_size.is_empty(){
+                    sizes.push(metadata.len());
+                }
+                same_size.push(file_path);
+            },
+            Ok(_) => {},
+            Err(error) => errors.push((file_path.clone(), error)),
+        }
+    }
+
+    let mut groups = vec![];
+    for size in sizes{
+        let same_size = &files_by_size[&size];
+        if same_size.len() < 2{
+            continue;
+        }
+        let mut digests: Vec<String> = vec![];
+        let mut files_by_digest: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for file_path in same_size{
+            match hashing::hash_file(file_path, HashAlgorithm::Blake3){
+                Ok(digest) => {
+                    let same_content = files_by_digest.entry(digest.clone()).or_default();
+                    if same_content.is_empty(){
+                        digests.push(digest);
+                    }
+                    same_content.push((*file_path).clone());
+                },
+                Err(error) => errors.push(((*file_path).clone(), error)),
+            }
+        }
+        for digest in digests{
+            let same_content = files_by_digest.remove(&digest).unwrap_or_default();
+            if same_content.len() > 1{
+                groups.push(DuplicateGroup{ Size: size, Files: same_content });
+            }
+        }
+    }
+    // groups follow the order of their original in the list
+    let position: HashMap<&PathBuf, usize> = files.iter().enumerate().map(|(index, file_path)| (file_path, index)).collect();
+    groups.sort_by_key(|group| position[&group.Files[0]]);
+    (groups, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+    use std::path::Path;
+
+    fn create_files(directory: &Path, files: &[(&str, &str)]) -> Vec<PathBuf>{
+        files.iter().map(|(name, content)| {
+            fs::write(directory.join(name), content).unwrap();
+            directory.join(name)
+        }).collect()
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let directory = test_directory("duplicates-find");
+        let files = create_files(&directory, &[
+            ("b.txt", "same"),
+            ("unique.txt", "other content"),
+            ("a.txt", "same"),
+            ("same-size.txt", "diff"),
+            ("photo.jpg", "image"),
+            ("empty1", ""),
+            ("empty2", ""),
+            ("photo (1).jpg", "image"),
+            ("b copy.txt", "same"),
+        ]);
+        let (groups, errors) = find_duplicates(&files);
+        assert!(errors.is_empty());
+        assert_eq!(groups, vec![
+            DuplicateGroup{ Size: 4, Files: vec![directory.join("b.txt"), directory.join("a.txt"), directory.join("b copy.txt")] },
+            DuplicateGroup{ Size: 5, Files: vec![directory.join("photo.jpg"), directory.join("photo (1).jpg")] },
+        ], "Files of the same size with another content and empty files are not duplicates.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicates_skips_directories_and_reports_missing_files() {
+        let directory = test_directory("duplicates-errors");
+        let mut files = create_files(&directory, &[("a", "x"), ("b", "x")]);
+        fs::create_dir(directory.join("folder")).unwrap();
+        files.push(directory.join("folder"));
+        files.push(directory.join("missing"));
+        let (groups, errors) = find_duplicates(&files);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, directory.join("missing"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
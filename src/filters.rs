@@ -1,32 +1,114 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::default;
 
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
 type IntPR = i32; // Precision
+// no file name can be longer, a wider counter could never be used
+const MAX_COUNTER_WIDTH: usize = 255;
 
 pub struct Counter{
     counter: IntPR,
     increment: IntPR,
+    // minimum number of digits, shorter values are padded with zeros
+    width: usize,
 }
 
 impl Counter{
     pub fn new(counter: IntPR, increment: IntPR) -> Counter{
         Counter{
-            counter: counter,
-            increment: increment,
+            counter,
+            increment,
+            width: 0,
         }
     }
 
+    pub fn with_width(mut self, width: usize) -> Counter{
+        self.width = width;
+        self
+    }
+
     pub fn count(&mut self) -> IntPR{
         let value = self.counter;
         self.counter += self.increment;
-        return value; 
+        value
+    }
+
+    pub fn count_formatted(&mut self) -> String{
+        let width = self.width;
+        format!("{:0width$}", self.count(), width = width)
+    }
+}
+
+// A filter starting with this flag ignores the case of the filenames, as with --ignore-case.
+pub const IGNORE_CASE_FLAG: &str = "(?i)";
+
+// Name given to the empty capture groups that stand in for counters in regex filters.
+const COUNTER_GROUP_PREFIX: &str = "__counter";
+
+fn get_char(string:&str, index:&usize)->char{string.chars().nth(*index).unwrap()}
+
+// Indexes count characters, not bytes, so names with accents, CJK or emoji are split on character boundaries.
+fn substring(string:&str, start:usize, end:usize)->String{string.chars().skip(start).take(end - start).collect()}
+
+// The same accented letter can be stored precomposed (NFC) or as a letter followed by a combining
+// mark (NFD, common on macOS), normalizing the filter and the filenames lets both forms match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization{
+    Nfc,
+    Nfd,
+}
+
+impl Normalization{
+    pub fn apply(&self, text: &str) -> String{
+        match self{
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+        }
     }
 }
 
-fn get_char(string:&String, index:&usize)->char{string.chars().nth(*index).unwrap()}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharacterClass{
+    Digit,
+    Letter,
+    Word,
+    Any,
+}
+
+impl CharacterClass{
+    fn contains(&self, character: char) -> bool{
+        match self{
+            CharacterClass::Digit => character.is_ascii_digit(),
+            CharacterClass::Letter => character.is_alphabetic(),
+            CharacterClass::Word => character.is_alphanumeric() || character == '_',
+            CharacterClass::Any => true,
+        }
+    }
+}
 
 enum WildcardType{
     Counter,
     String,
+    // <d> <a> <w> match one or more digits, letters or word characters, <d4> exactly four, <?> one character
+    Typed(CharacterClass, Option<usize>),
+}
+
+impl WildcardType{
+    fn accepts(&self, text: &str) -> bool{
+        match self{
+            WildcardType::Counter | WildcardType::String => true,
+            WildcardType::Typed(class, length) => {
+                let count = text.chars().count();
+                text.chars().all(|character| class.contains(character)) && match length{
+                    Some(length) => count == *length,
+                    None => count > 0,
+                }
+            },
+        }
+    }
 }
 
 pub struct RenameFilter{
@@ -34,6 +116,12 @@ pub struct RenameFilter{
     fixed_str: Vec<String>,
     counters: Vec<Counter>,
     wildcard_type: Vec<WildcardType>,
+    // wildcards written twice (**) are greedy, the others lazy
+    greedy: Vec<bool>,
+    regex: Option<Regex>,
+    wildcard_names: HashMap<String, usize>,
+    normalization: Option<Normalization>,
+    ignore_case: bool,
 }
 
 enum AutomataResult{
@@ -41,17 +129,21 @@ enum AutomataResult{
     Failure,
 }
 
-fn rename_filter_find_counter_automata(end_index: &mut usize, string_representation: &String, counter_start: &mut IntPR, counter_increment: &mut IntPR)->AutomataResult{
+fn rename_filter_find_counter_automata(end_index: &mut usize, string_representation: &str, counter_start: &mut IntPR, counter_increment: &mut IntPR, counter_width: &mut usize)->AutomataResult{
     let mut state = 1;
+    let length = string_representation.chars().count();
     loop {
         *end_index += 1;
+        if *end_index >= length{
+            break;
+        }
         match state {
             1 => {
                 if get_char(string_representation, end_index) == ' '{
                     continue;
                 }
-                if get_char(string_representation, end_index).is_digit(10){
-                    *counter_start = get_char(string_representation, &end_index).to_digit(10).unwrap() as IntPR;
+                if get_char(string_representation, end_index).is_ascii_digit(){
+                    *counter_start = get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR;
                     state = 2;
                 }
                 else{
@@ -59,8 +151,12 @@ fn rename_filter_find_counter_automata(end_index: &mut usize, string_representat
                 }
             }
             2 => {
-                if get_char(string_representation, end_index).is_digit(10){
-                    *counter_start = *counter_start * 10 + get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR;
+                if get_char(string_representation, end_index).is_ascii_digit(){
+                    // numbers too big to be stored keep the counter as text
+                    let Some(new_start) = counter_start.checked_mul(10).and_then(|start| start.checked_add(get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR)) else {
+                        return AutomataResult::Failure;
+                    };
+                    *counter_start = new_start;
                     continue;
                 }
                 if get_char(string_representation, end_index) == ' '{
@@ -89,7 +185,7 @@ fn rename_filter_find_counter_automata(end_index: &mut usize, string_representat
                 if get_char(string_representation, end_index) == ' '{
                     continue;
                 }
-                if get_char(string_representation, end_index).is_digit(10){
+                if get_char(string_representation, end_index).is_ascii_digit(){
                     *counter_increment = get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR;
                     state = 5;
                 }
@@ -98,14 +194,21 @@ fn rename_filter_find_counter_automata(end_index: &mut usize, string_representat
                 }
             }
             5 => {
-                if get_char(string_representation, end_index).is_digit(10){
-                    *counter_increment = *counter_increment * 10 + get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR;
+                if get_char(string_representation, end_index).is_ascii_digit(){
+                    let Some(new_increment) = counter_increment.checked_mul(10).and_then(|increment| increment.checked_add(get_char(string_representation, end_index).to_digit(10).unwrap() as IntPR)) else {
+                        return AutomataResult::Failure;
+                    };
+                    *counter_increment = new_increment;
                     continue;
                 }
                 if get_char(string_representation, end_index) == ' '{
                     state = 6;
                     continue;
                 }
+                if get_char(string_representation, end_index) == ':'{
+                    state = 8;
+                    continue;
+                }
                 if get_char(string_representation, end_index) == '}'{
                     state = 7;
                     break;
@@ -115,6 +218,54 @@ fn rename_filter_find_counter_automata(end_index: &mut usize, string_representat
                 }
             }
             6 => {
+                if get_char(string_representation, end_index) == ' '{
+                    continue;
+                }
+                if get_char(string_representation, end_index) == ':'{
+                    state = 8;
+                    continue;
+                }
+                if get_char(string_representation, end_index) == '}'{
+                    state = 7;
+                    break;
+                }
+                else{
+                    break;
+                }
+            }
+            8 => {
+                if get_char(string_representation, end_index) == ' '{
+                    continue;
+                }
+                if get_char(string_representation, end_index).is_ascii_digit(){
+                    *counter_width = get_char(string_representation, end_index).to_digit(10).unwrap() as usize;
+                    state = 9;
+                }
+                else{
+                    break;
+                }
+            }
+            9 => {
+                if get_char(string_representation, end_index).is_ascii_digit(){
+                    match counter_width.checked_mul(10).and_then(|width| width.checked_add(get_char(string_representation, end_index).to_digit(10).unwrap() as usize)){
+                        Some(new_width) if new_width <= MAX_COUNTER_WIDTH => *counter_width = new_width,
+                        _ => return AutomataResult::Failure,
+                    }
+                    continue;
+                }
+                if get_char(string_representation, end_index) == ' '{
+                    state = 10;
+                    continue;
+                }
+                if get_char(string_representation, end_index) == '}'{
+                    state = 7;
+                    break;
+                }
+                else{
+                    break;
+                }
+            }
+            10 => {
                 if get_char(string_representation, end_index) == ' '{
                     continue;
                 }
@@ -129,11 +280,123 @@ fn rename_filter_find_counter_automata(end_index: &mut usize, string_representat
             _ => break
             }
         }
-        print!("state: {}", state);
         match state{
-            7 => return AutomataResult::Success,
+            7 => AutomataResult::Success,
+            _ => AutomataResult::Failure,
+        }
+}
+
+fn rename_filter_find_label_automata(end_index: &mut usize, string_representation: &str, label: &mut String)->AutomataResult{
+    // automata  '{' -> ' '* -> (letter|'_') -> (letter|digit|'_')* -> ' '* -> '}'
+    let mut state = 1;
+    let length = string_representation.chars().count();
+    loop {
+        *end_index += 1;
+        if *end_index >= length{
+            return AutomataResult::Failure;
+        }
+        let character = get_char(string_representation, end_index);
+        match state {
+            1 => {
+                if character == ' '{
+                    continue;
+                }
+                if character.is_alphabetic() || character == '_'{
+                    label.push(character);
+                    state = 2;
+                    continue;
+                }
+                return AutomataResult::Failure;
+            }
+            2 => {
+                if character.is_alphanumeric() || character == '_'{
+                    label.push(character);
+                    continue;
+                }
+                if character == ' '{
+                    state = 3;
+                    continue;
+                }
+                if character == '}'{
+                    return AutomataResult::Success;
+                }
+                return AutomataResult::Failure;
+            }
+            3 => {
+                if character == ' '{
+                    continue;
+                }
+                if character == '}'{
+                    return AutomataResult::Success;
+                }
+                return AutomataResult::Failure;
+            }
             _ => return AutomataResult::Failure,
         }
+    }
+}
+
+fn rename_filter_find_typed_wildcard_automata(end_index: &mut usize, string_representation: &str, class: &mut CharacterClass, wildcard_length: &mut Option<usize>)->AutomataResult{
+    // automata  '<' -> ('d'|'a'|'w'|'?') -> digit* -> '>'
+    let mut state = 1;
+    let length = string_representation.chars().count();
+    loop {
+        *end_index += 1;
+        if *end_index >= length{
+            return AutomataResult::Failure;
+        }
+        let character = get_char(string_representation, end_index);
+        match state {
+            1 => {
+                *class = match character{
+                    'd' => CharacterClass::Digit,
+                    'a' => CharacterClass::Letter,
+                    'w' => CharacterClass::Word,
+                    '?' => CharacterClass::Any,
+                    _ => return AutomataResult::Failure,
+                };
+                state = 2;
+            }
+            2 => {
+                if character.is_ascii_digit(){
+                    // a length too big to be stored could never match, the wildcard is kept as text
+                    let Some(new_length) = wildcard_length.unwrap_or(0).checked_mul(10).and_then(|length| length.checked_add(character.to_digit(10).unwrap() as usize)) else {
+                        return AutomataResult::Failure;
+                    };
+                    *wildcard_length = Some(new_length);
+                    continue;
+                }
+                if character == '>'{
+                    // <?> is a single character, a length of 0 would never match anything
+                    if *class == CharacterClass::Any && wildcard_length.is_none(){
+                        *wildcard_length = Some(1);
+                    }
+                    if *wildcard_length == Some(0){
+                        return AutomataResult::Failure;
+                    }
+                    return AutomataResult::Success;
+                }
+                return AutomataResult::Failure;
+            }
+            _ => return AutomataResult::Failure,
+        }
+    }
+}
+
+// Looks for a `{name}` label right after a wildcard, on success end_index points past the label.
+fn find_wildcard_label(end_index: &mut usize, string_representation: &str) -> Option<String>{
+    if *end_index >= string_representation.chars().count() || get_char(string_representation, end_index) != '{'{
+        return None;
+    }
+    let mut label_end = *end_index;
+    let mut label = String::new();
+    match rename_filter_find_label_automata(&mut label_end, string_representation, &mut label){
+        AutomataResult::Success => {
+            *end_index = label_end + 1;
+            Some(label)
+        },
+        AutomataResult::Failure => None,
+    }
 }
 
 impl RenameFilter{
@@ -143,129 +406,353 @@ impl RenameFilter{
             fixed_str: vec![],
             counters: vec![],
             wildcard_type: vec![],
+            greedy: vec![],
+            regex: None,
+            wildcard_names: HashMap::new(),
+            normalization: None,
+            ignore_case: false,
         };
 
 
         let mut start_index = 0;
         let mut end_index = 0;
-        while end_index  < rf.string_representation.len(){
+        let length = rf.string_representation.chars().count();
+        if rf.string_representation.starts_with(IGNORE_CASE_FLAG){
+            rf.ignore_case = true;
+            start_index = IGNORE_CASE_FLAG.chars().count();
+            end_index = start_index;
+        }
+        while end_index < length{
             // case where wildcard is found
             if get_char(&rf.string_representation, &end_index) == wildcard_char{
-                rf.fixed_str.push(rf.string_representation[start_index..end_index].to_string());
-                start_index = end_index + 1;
-                end_index = end_index + 1;
+                rf.fixed_str.push(substring(&rf.string_representation, start_index, end_index));
+                end_index += 1;
+                let greedy = end_index < length && get_char(&rf.string_representation, &end_index) == wildcard_char;
+                if greedy{
+                    end_index += 1;
+                }
                 rf.wildcard_type.push(WildcardType::String);
+                rf.greedy.push(greedy);
+                rf.label_last_wildcard(&mut end_index);
+                start_index = end_index;
                 continue;
             }
+            // case where a typed wildcard could be found
+            if get_char(&rf.string_representation, &end_index) == '<'{
+                let starting_typed_wildcard = end_index;
+                let mut class = CharacterClass::Any;
+                let mut wildcard_length = None;
+                match rename_filter_find_typed_wildcard_automata(&mut end_index, &rf.string_representation, &mut class, &mut wildcard_length){
+                    AutomataResult::Success => {
+                        rf.fixed_str.push(substring(&rf.string_representation, start_index, starting_typed_wildcard));
+                        rf.wildcard_type.push(WildcardType::Typed(class, wildcard_length));
+                        rf.greedy.push(false);
+                        end_index += 1;
+                        rf.label_last_wildcard(&mut end_index);
+                        start_index = end_index;
+                        continue;
+                    },
+                    AutomataResult::Failure => {
+                        end_index = starting_typed_wildcard;
+                    }
+                }
+            }
             // case where counter could be found
             if get_char(&rf.string_representation, &end_index) == '{'{
                 let starting_counter_wildcard = end_index;
-                // automata  '{' -> ' '* -> digit+ -> ' '* -> ':' -> ' '* -> digit+ -> ' '* -> '}'
+                // automata  '{' -> ' '* -> digit+ -> ' '* -> ':' -> ' '* -> digit+ -> ' '* -> (':' -> ' '* -> digit+ -> ' '*)? -> '}'
                 let mut counter_start:IntPR = 0;
                 let mut counter_increment:IntPR = 0;
-                match rename_filter_find_counter_automata(&mut end_index, &rf.string_representation, &mut counter_start, &mut counter_increment){
+                let mut counter_width:usize = 0;
+                match rename_filter_find_counter_automata(&mut end_index, &rf.string_representation, &mut counter_start, &mut counter_increment, &mut counter_width){
                     AutomataResult::Success => {
-                        rf.fixed_str.push(rf.string_representation[start_index..starting_counter_wildcard].to_string());
-                        rf.counters.push(Counter::new(counter_start, counter_increment));
+                        rf.fixed_str.push(substring(&rf.string_representation, start_index, starting_counter_wildcard));
+                        rf.counters.push(Counter::new(counter_start, counter_increment).with_width(counter_width));
                         rf.wildcard_type.push(WildcardType::Counter);
-                        start_index = end_index + 1;
-                        end_index = end_index + 1;
+                        rf.greedy.push(false);
+                        end_index += 1;
+                        rf.label_last_wildcard(&mut end_index);
+                        start_index = end_index;
                         continue;
                     },
-                    AutomataResult::Failure => {}
+                    AutomataResult::Failure => {
+                        end_index = starting_counter_wildcard;
+                    }
                 }
             }
             end_index += 1;
         }
-        rf.fixed_str.push(rf.string_representation[start_index..].to_string());
+        rf.fixed_str.push(substring(&rf.string_representation, start_index, length));
 
         rf
     }
 
+    // A wildcard or counter directly followed by `{name}` can be referenced by name in the renamer.
+    // When a name is repeated the first definition is kept.
+    fn label_last_wildcard(&mut self, end_index: &mut usize){
+        if let Some(label) = find_wildcard_label(end_index, &self.string_representation){
+            self.wildcard_names.entry(label).or_insert(self.wildcard_type.len() - 1);
+        }
+    }
+
+    // Regex mode: every capture group is a wildcard and the expression must match the whole filename.
+    // Counters ({start:inc}) are replaced by an empty group that yields the counter value.
+    // Named groups and labeled counters ({start:inc}{name}) can be referenced by name in the renamer.
+    pub fn new_regex(string_representation: String) -> Result<Self, regex::Error>{
+        let characters: Vec<char> = string_representation.chars().collect();
+        let mut counters = vec![];
+        let mut counter_labels: HashMap<String, String> = HashMap::new();
+        let mut pattern = String::new();
+
+        let mut end_index = 0;
+        while end_index < characters.len(){
+            // escaped characters are copied verbatim
+            if characters[end_index] == '\\' && end_index + 1 < characters.len(){
+                pattern.push(characters[end_index]);
+                pattern.push(characters[end_index + 1]);
+                end_index += 2;
+                continue;
+            }
+            if characters[end_index] == '{'{
+                let starting_counter_wildcard = end_index;
+                let mut counter_start:IntPR = 0;
+                let mut counter_increment:IntPR = 0;
+                let mut counter_width:usize = 0;
+                match rename_filter_find_counter_automata(&mut end_index, &string_representation, &mut counter_start, &mut counter_increment, &mut counter_width){
+                    AutomataResult::Success => {
+                        let group_name = format!("{}{}", COUNTER_GROUP_PREFIX, counters.len());
+                        pattern.push_str(&format!("(?P<{}>)", group_name));
+                        counters.push(Counter::new(counter_start, counter_increment).with_width(counter_width));
+                        end_index += 1;
+                        if let Some(label) = find_wildcard_label(&mut end_index, &string_representation){
+                            counter_labels.insert(group_name, label);
+                        }
+                        continue;
+                    },
+                    AutomataResult::Failure => {
+                        end_index = starting_counter_wildcard;
+                    }
+                }
+            }
+            pattern.push(characters[end_index]);
+            end_index += 1;
+        }
+
+        let regex = Regex::new(&format!("^(?:{})$", pattern))?;
+        let wildcard_type = regex.capture_names()
+            .skip(1)
+            .map(|name| match name{
+                Some(name) if name.starts_with(COUNTER_GROUP_PREFIX) => WildcardType::Counter,
+                _ => WildcardType::String,
+            })
+            .collect();
+        let mut wildcard_names = HashMap::new();
+        for (group_index, name) in regex.capture_names().enumerate().skip(1){
+            let label = match name{
+                Some(name) if name.starts_with(COUNTER_GROUP_PREFIX) => counter_labels.remove(name),
+                Some(name) => Some(name.to_string()),
+                None => None,
+            };
+            if let Some(label) = label{
+                wildcard_names.entry(label).or_insert(group_index - 1);
+            }
+        }
+
+        Ok(RenameFilter{
+            string_representation,
+            fixed_str: vec![],
+            counters,
+            wildcard_type,
+            greedy: vec![],
+            regex: Some(regex),
+            wildcard_names,
+            normalization: None,
+            ignore_case: false,
+        })
+    }
+
+    // Filenames are normalized before they are matched, the catched strings come out normalized too.
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self{
+        self.fixed_str = self.fixed_str.iter().map(|fixed_str| normalization.apply(fixed_str)).collect();
+        if let Some(regex) = &self.regex{
+            // the generated group names are ASCII and stay the same
+            if let Ok(normalized) = Regex::new(&normalization.apply(regex.as_str())){
+                self.regex = Some(normalized);
+            }
+        }
+        self.normalization = Some(normalization);
+        self
+    }
+
+    // Fixed strings match regardless of case, the catched strings keep the case of the filename.
+    pub fn with_ignore_case(mut self) -> Self{
+        if let Some(regex) = &self.regex{
+            if let Ok(case_insensitive) = Regex::new(&format!("{}{}", IGNORE_CASE_FLAG, regex.as_str())){
+                self.regex = Some(case_insensitive);
+            }
+        }
+        self.ignore_case = true;
+        self
+    }
+
+    fn normalize<'a>(&self, filename: &'a str) -> Cow<'a, str>{
+        match &self.normalization{
+            Some(normalization) => Cow::Owned(normalization.apply(filename)),
+            None => Cow::Borrowed(filename),
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn get_fixed_str(&self) -> Vec<String>{
         self.fixed_str.clone()
     }
 
+    pub fn get_wildcard_names(&self) -> HashMap<String, usize>{
+        self.wildcard_names.clone()
+    }
+
+    // Number of wildcards and counters, the positions a new name can select.
+    pub fn get_wildcard_count(&self) -> usize{
+        self.wildcard_type.len()
+    }
+
     pub fn does_fulfill(&self, filename: &str) -> bool{
-        let mut filename_index = 0;
-        for fixed_str in &self.fixed_str[..self.fixed_str.len()-1]{
-            let index = filename[filename_index..].find(fixed_str);
-            match index{
-                Some(i) => filename_index += i + fixed_str.len(),
-                None => return false,
+        let filename = &*self.normalize(filename);
+        if let Some(regex) = &self.regex{
+            return regex.is_match(filename);
+        }
+        self.match_wildcards(filename).is_some()
+    }
+
+    // Byte ranges of the filename taken by each wildcard, or None when the filter does not match.
+    // The fixed strings must match exactly and in order, the first one at the start and the last one at the end.
+    fn match_wildcards(&self, filename: &str) -> Option<Vec<(usize, usize)>>{
+        let first_length = self.match_fixed(filename, &self.fixed_str[0])?;
+        if self.wildcard_type.is_empty(){
+            return (filename.len() == first_length).then(Vec::new);
+        }
+        let mut spans = vec![];
+        let mut failed = HashSet::new();
+        match self.match_wildcard(filename, first_length, 0, &mut spans, &mut failed){
+            true => Some(spans),
+            false => None,
+        }
+    }
+
+    // Number of bytes of the start of text matched by the fixed string, compared character by
+    // character so the catched strings around it keep the case of the filename.
+    fn match_fixed(&self, text: &str, fixed: &str) -> Option<usize>{
+        if !self.ignore_case{
+            return text.starts_with(fixed).then_some(fixed.len());
+        }
+        let mut text_characters = text.char_indices();
+        for fixed_character in fixed.chars(){
+            match text_characters.next(){
+                Some((_, character)) if character == fixed_character || character.to_lowercase().eq(fixed_character.to_lowercase()) => {},
+                _ => return None,
+            }
+        }
+        Some(text_characters.next().map_or(text.len(), |(index, _)| index))
+    }
+
+    // Tries every end for the wildcard starting at `start` that lets the following fixed string and the rest of
+    // the filter match, backtracking on failure. Lazy wildcards try the shortest text first, greedy ones the longest.
+    // Starts that already failed are remembered so a filename is never matched in more than polynomial time.
+    fn match_wildcard(&self, filename: &str, start: usize, wildcard_index: usize, spans: &mut Vec<(usize, usize)>, failed: &mut HashSet<(usize, usize)>) -> bool{
+        if failed.contains(&(wildcard_index, start)){
+            return false;
+        }
+        let next_fixed = self.fixed_str[wildcard_index + 1].as_str();
+        let is_last = wildcard_index + 1 == self.wildcard_type.len();
+        // pairs of wildcard end and end of the following fixed string
+        let mut ends: Vec<(usize, usize)> = if is_last{
+            // the last fixed string has to end the filename, it takes as many characters as it has
+            let end = match next_fixed.chars().count(){
+                0 => Some(filename.len()),
+                count => filename.char_indices().rev().nth(count - 1).map(|(index, _)| index),
+            };
+            match end{
+                Some(end) if end >= start && self.match_fixed(&filename[end..], next_fixed) == Some(filename.len() - end) => vec![(end, filename.len())],
+                _ => vec![],
             }
         }
-        if &self.fixed_str[self.fixed_str.len()-1] == ""{
-            return true;
+        else{
+            (start..=filename.len())
+                .filter(|end| filename.is_char_boundary(*end))
+                .filter_map(|end| self.match_fixed(&filename[end..], next_fixed).map(|length| (end, end + length)))
+                .collect()
+        };
+        ends.retain(|(end, _)| self.wildcard_type[wildcard_index].accepts(&filename[start..*end]));
+        if self.greedy.get(wildcard_index).copied().unwrap_or(false){
+            ends.reverse();
         }
-        let index = filename[filename_index..].find(&self.fixed_str[self.fixed_str.len()-1]);
-        match index{
-            Some(i) => return filename[filename_index+i..] == self.fixed_str[self.fixed_str.len()-1],
-            None => return false,
+        for (end, next_start) in ends{
+            spans.push((start, end));
+            if is_last || self.match_wildcard(filename, next_start, wildcard_index + 1, spans, failed){
+                return true;
+            }
+            spans.pop();
         }
+        failed.insert((wildcard_index, start));
+        false
     }
 
     pub fn collect_wildcards(&mut self, filename: &str) -> Vec<String>{
+        let mut counter_index = 0;
+        let mut catched_wildcards = vec![];
+        for catched in self.peek_wildcards(filename){
+            match catched{
+                Some(catched) => catched_wildcards.push(catched),
+                None => {
+                    catched_wildcards.push(self.counters[counter_index].count_formatted());
+                    counter_index += 1;
+                }
+            }
+        }
+        catched_wildcards
+    }
+
+    // Same as collect_wildcards but counters are left untouched and show up as None.
+    pub fn peek_wildcards(&self, filename: &str) -> Vec<Option<String>>{
         if self.wildcard_type.is_empty(){
             return vec![];
         }
+        let filename = &*self.normalize(filename);
+        if self.regex.is_some(){
+            return self.peek_regex_wildcards(filename);
+        }
 
-        let mut filename_index = 0;
-        let mut wildcard_index = 0;
-        let mut counter_index = 0;
-        let mut catched_wildcards = vec![];
-        
-        let index = filename[filename_index..].find(&self.fixed_str[0]);
-        match index{
-            Some(i) => filename_index += i + self.fixed_str[0].len(),
+        let spans = match self.match_wildcards(filename){
+            Some(spans) => spans,
             None => return vec![],
-        }
+        };
+        self.wildcard_type.iter().zip(spans)
+            .map(|(wildcard_type, (start, end))| match wildcard_type{
+                WildcardType::Counter => None,
+                WildcardType::String | WildcardType::Typed(..) => Some(filename[start..end].to_string()),
+            })
+            .collect()
+    }
 
-        for fixed_str in &self.fixed_str[1..self.fixed_str.len()-1]{
-            let index = filename[filename_index..].find(fixed_str);
-            match index{
-                Some(i) => {
-                    match &self.wildcard_type[wildcard_index]{
-                        WildcardType::Counter =>{
-                            catched_wildcards.push(self.counters[counter_index].count().to_string());
-                            counter_index += 1;
-                        }
-                        WildcardType::String =>{
-                            catched_wildcards.push(filename[filename_index..filename_index+i].to_string());
-                        }
-                    }
-                    wildcard_index += 1;
-                    filename_index += i + fixed_str.len();
-                },
-                None => return vec![],
-            }
-        }
-        if &self.fixed_str[self.fixed_str.len()-1] == ""{
-            match &self.wildcard_type[wildcard_index]{
+    fn peek_regex_wildcards(&self, filename: &str) -> Vec<Option<String>>{
+        let captures = match self.regex.as_ref().unwrap().captures(filename){
+            Some(captures) => captures,
+            None => return vec![],
+        };
+        let mut catched_wildcards = vec![];
+        for (group_index, wildcard_type) in self.wildcard_type.iter().enumerate(){
+            match wildcard_type{
                 WildcardType::Counter =>{
-                    catched_wildcards.push(self.counters[counter_index].count().to_string());
+                    catched_wildcards.push(None);
                 }
-                WildcardType::String =>{
-                    catched_wildcards.push(filename[filename_index..].to_string());
+                WildcardType::String | WildcardType::Typed(..) =>{
+                    // groups that did not participate in the match are treated as empty
+                    let catched = captures.get(group_index + 1).map_or("", |m| m.as_str());
+                    catched_wildcards.push(Some(catched.to_string()));
                 }
             }
-            return  catched_wildcards;
-        }
-        let index = filename[filename_index..].find(&self.fixed_str[self.fixed_str.len()-1]);
-        match index{
-            Some(i) => {
-                match &self.wildcard_type[wildcard_index]{
-                    WildcardType::Counter =>{
-                        catched_wildcards.push(self.counters[counter_index].count().to_string());
-                    }
-                    WildcardType::String =>{
-                        catched_wildcards.push(filename[filename_index..filename_index+i].to_string());
-                    }
-                }
-            },
-            None => return vec![],
         }
-        return catched_wildcards;
+        catched_wildcards
     }
 }
 
@@ -276,6 +763,11 @@ impl default::Default for RenameFilter{
             fixed_str: vec![],
             counters: vec![],
             wildcard_type: vec![],
+            greedy: vec![],
+            regex: None,
+            wildcard_names: HashMap::new(),
+            normalization: None,
+            ignore_case: false,
         }
     }
     
@@ -427,5 +919,288 @@ mod tests {
         assert_eq!(filter.counters[1].counter, 10, "Second counter starts at 10.");
         assert_eq!(filter.counters[1].increment, 5, "Second counter increments by 5.");
     }
-}
 
+    #[test]
+    fn test_rename_filter_new_with_unterminated_counter() {
+        let filter = RenameFilter::new("file_{1:1".to_string(), '*');
+        assert!(filter.counters.is_empty(), "A counter without its closing brace is kept as text.");
+        assert_eq!(filter.fixed_str, vec!["file_{1:1"]);
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_capture_groups() {
+        let mut filter = RenameFilter::new_regex(r"IMG_(\d+)\.(jpg|jpeg)".to_string()).unwrap();
+        assert!(filter.does_fulfill("IMG_0042.jpeg"), "Matches digits and the extension alternation.");
+        assert!(!filter.does_fulfill("IMG_00a2.jpg"), "Does not match letters where digits are expected.");
+        assert!(!filter.does_fulfill("old_IMG_0042.jpg"), "The expression must match the whole filename.");
+        assert_eq!(filter.collect_wildcards("IMG_0042.jpeg"), vec!["0042", "jpeg"], "Capture groups are collected in order.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_optional_group() {
+        let mut filter = RenameFilter::new_regex(r"(\w+?)(-copy)?\.txt".to_string()).unwrap();
+        assert_eq!(filter.collect_wildcards("notes-copy.txt"), vec!["notes", "-copy"], "Optional group is captured when present.");
+        assert_eq!(filter.collect_wildcards("notes.txt"), vec!["notes", ""], "Optional group is empty when absent.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_with_counters() {
+        let mut filter = RenameFilter::new_regex(r"{1:1}photo_(\d{2})\.png{10:5}".to_string()).unwrap();
+        assert!(matches!(filter.wildcard_type[0], WildcardType::Counter), "First wildcard is a counter.");
+        assert!(matches!(filter.wildcard_type[1], WildcardType::String), "Second wildcard is the capture group.");
+        assert!(matches!(filter.wildcard_type[2], WildcardType::Counter), "Third wildcard is a counter.");
+        assert!(filter.does_fulfill("photo_12.png"), "Counters do not consume characters.");
+        assert_eq!(filter.collect_wildcards("photo_12.png"), vec!["1", "12", "10"]);
+        assert_eq!(filter.collect_wildcards("photo_34.png"), vec!["2", "34", "15"]);
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_escaped_counter() {
+        let filter = RenameFilter::new_regex(r"a\{1:1\}".to_string()).unwrap();
+        assert!(filter.counters.is_empty(), "Escaped braces are not counters.");
+        assert!(filter.does_fulfill("a{1:1}"), "Escaped braces match literally.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_invalid() {
+        assert!(RenameFilter::new_regex("photo_(".to_string()).is_err(), "Invalid expressions are reported.");
+        assert!(RenameFilter::new_regex("photo_{".to_string()).is_err(), "A dangling brace is reported instead of panicking.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_with_labels() {
+        let mut filter = RenameFilter::new("photo-*{day}-*{ month }-*{year}.png".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["photo-", "-", "-", ".png"], "Labels are not part of the fixed strings.");
+        let names = filter.get_wildcard_names();
+        assert_eq!(names.get("day"), Some(&0));
+        assert_eq!(names.get("month"), Some(&1));
+        assert_eq!(names.get("year"), Some(&2));
+        assert_eq!(filter.collect_wildcards("photo-01-02-2021.png"), vec!["01", "02", "2021"]);
+    }
+
+    #[test]
+    fn test_rename_filter_new_with_labeled_counter() {
+        let filter = RenameFilter::new("*{name}_{1:1}{index}.txt".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["", "_", ".txt"]);
+        let names = filter.get_wildcard_names();
+        assert_eq!(names.get("name"), Some(&0));
+        assert_eq!(names.get("index"), Some(&1));
+    }
+
+    #[test]
+    fn test_rename_filter_new_counter_after_wildcard_is_not_a_label() {
+        let filter = RenameFilter::new("*{2:1}".to_string(), '*');
+        assert_eq!(filter.wildcard_type.len(), 2, "A counter right after a wildcard is still a counter.");
+        assert!(filter.get_wildcard_names().is_empty());
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_named_groups() {
+        let filter = RenameFilter::new_regex(r"{1:1}{index}(?P<day>\d+)-(\d+)-(?<year>\d+)".to_string()).unwrap();
+        let names = filter.get_wildcard_names();
+        assert_eq!(names.get("index"), Some(&0), "Labeled counters are named.");
+        assert_eq!(names.get("day"), Some(&1));
+        assert_eq!(names.get("year"), Some(&3));
+        assert_eq!(names.len(), 3, "Unnamed groups are not in the name table.");
+    }
+
+    #[test]
+    fn test_peek_wildcards_leaves_counters_untouched() {
+        let mut filter = RenameFilter::new("file_*_{1:1}".to_string(), '*');
+        assert_eq!(filter.peek_wildcards("file_abc_x"), vec![Some("abc".to_string()), None]);
+        assert_eq!(filter.peek_wildcards("file_abc_x"), vec![Some("abc".to_string()), None]);
+        assert_eq!(filter.collect_wildcards("file_abc_x"), vec!["abc", "1"], "Peeking does not advance the counter.");
+    }
+
+    #[test]
+    fn test_counter_with_width() {
+        let mut counter = Counter::new(7, 3).with_width(3);
+        assert_eq!(counter.count_formatted(), "007", "Values are padded with zeros.");
+        assert_eq!(counter.count_formatted(), "010");
+        let mut counter = Counter::new(1234, 1).with_width(2);
+        assert_eq!(counter.count_formatted(), "1234", "Longer values are not truncated.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_with_counter_width() {
+        let mut filter = RenameFilter::new("img{1:1:04}_{ 5 : 5 : 3 }.png".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["img", "_", ".png"]);
+        assert_eq!(filter.counters[0].width, 4, "Leading zero of the width is optional.");
+        assert_eq!(filter.counters[1].width, 3);
+        assert_eq!(filter.collect_wildcards("img_.png"), vec!["0001", "005"]);
+        assert_eq!(filter.collect_wildcards("img_.png"), vec!["0002", "010"]);
+    }
+
+    #[test]
+    fn test_rename_filter_new_regex_with_counter_width() {
+        let mut filter = RenameFilter::new_regex(r"photo_\d+\.jpg{1:1:3}".to_string()).unwrap();
+        assert_eq!(filter.collect_wildcards("photo_7.jpg"), vec!["001"]);
+    }
+
+    #[test]
+    fn test_rename_filter_new_with_overflowing_counter() {
+        for representation in ["a{1:1:99999999999999999999}", "a{1:1:999999999}", "a{99999999999:1}", "a{1:99999999999}"]{
+            let filter = RenameFilter::new(representation.to_string(), '*');
+            assert!(filter.counters.is_empty(), "{} is kept as text.", representation);
+            assert!(filter.does_fulfill(representation));
+        }
+        let filter = RenameFilter::new(format!("a{{1:1:{}}}", MAX_COUNTER_WIDTH), '*');
+        assert_eq!(filter.counters[0].width, MAX_COUNTER_WIDTH);
+    }
+
+    #[test]
+    fn test_rename_filter_new_unicode() {
+        let mut filter = RenameFilter::new("日本_*{曲}-{1:1}_ñ*.mp3".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["日本_", "-", "_ñ", ".mp3"]);
+        assert_eq!(filter.get_wildcard_names().get("曲"), Some(&0), "Labels can use any letter.");
+        assert!(filter.does_fulfill("日本_さくら-_ñandú 🎵.mp3"));
+        assert_eq!(filter.collect_wildcards("日本_さくら-_ñandú 🎵.mp3"), vec!["さくら", "1", "andú 🎵"]);
+    }
+
+    #[test]
+    fn test_rename_filter_unicode_wildcard_char() {
+        let mut filter = RenameFilter::new("été★.txt".to_string(), '★');
+        assert_eq!(filter.fixed_str, vec!["été", ".txt"]);
+        assert_eq!(filter.collect_wildcards("été🌞.txt"), vec!["🌞"]);
+    }
+
+    #[test]
+    fn test_rename_filter_failed_counter_keeps_wildcard() {
+        let filter = RenameFilter::new("{*}".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["{", "}"], "A brace that does not start a counter is kept as text.");
+    }
+
+    #[test]
+    fn test_rename_filter_normalization() {
+        let decomposed = "cafe\u{301}_1.txt";
+        let filter = RenameFilter::new("café_*.txt".to_string(), '*');
+        assert!(!filter.does_fulfill(decomposed), "Without normalization the two forms differ.");
+
+        let mut filter = RenameFilter::new("café_*.txt".to_string(), '*').with_normalization(Normalization::Nfc);
+        assert!(filter.does_fulfill(decomposed));
+        assert_eq!(filter.collect_wildcards(decomposed), vec!["1"]);
+
+        let mut filter = RenameFilter::new("*_1.txt".to_string(), '*').with_normalization(Normalization::Nfd);
+        assert_eq!(filter.collect_wildcards("café_1.txt"), vec!["cafe\u{301}"]);
+    }
+
+    #[test]
+    fn test_rename_filter_regex_normalization() {
+        let mut filter = RenameFilter::new_regex("café_(\\d+)\\.txt".to_string()).unwrap().with_normalization(Normalization::Nfd);
+        assert_eq!(filter.collect_wildcards("café_7.txt"), vec!["7"]);
+        assert_eq!(filter.collect_wildcards("cafe\u{301}_8.txt"), vec!["8"]);
+    }
+
+    #[test]
+    fn test_matcher_backtracks() {
+        let mut filter = RenameFilter::new("*-*.tar.gz".to_string(), '*');
+        assert!(filter.does_fulfill("my-file-v2.tar.gz"));
+        assert_eq!(filter.collect_wildcards("my-file-v2.tar.gz"), vec!["my", "file-v2"], "Wildcards are lazy by default.");
+
+        let filter = RenameFilter::new("*_x_*_y".to_string(), '*');
+        assert!(filter.does_fulfill("a_x_b_x_c_y"), "A later occurrence of a fixed string is tried when the first one fails.");
+        assert_eq!(filter.peek_wildcards("a_x_b_x_c_y"), vec![Some("a".to_string()), Some("b_x_c".to_string())]);
+        assert!(!filter.does_fulfill("a_x_b_y_c"), "The last fixed string has to end the filename.");
+        let filter = RenameFilter::new("a_*_y".to_string(), '*');
+        assert!(!filter.does_fulfill("pre_a_x_y"), "The first fixed string has to start the filename.");
+    }
+
+    #[test]
+    fn test_matcher_greedy_wildcards() {
+        let mut filter = RenameFilter::new("**-*.tar.gz".to_string(), '*');
+        assert_eq!(filter.wildcard_type.len(), 2, "A doubled wildcard is a single greedy wildcard.");
+        assert_eq!(filter.collect_wildcards("my-file-v2.tar.gz"), vec!["my-file", "v2"]);
+
+        let mut filter = RenameFilter::new("**{name}.*".to_string(), '*');
+        assert_eq!(filter.get_wildcard_names().get("name"), Some(&0), "Greedy wildcards can be labeled.");
+        assert_eq!(filter.collect_wildcards("archive.tar.gz"), vec!["archive.tar", "gz"]);
+    }
+
+    #[test]
+    fn test_does_fulfill_agrees_with_collect_wildcards() {
+        let filters = ["*-*.tar.gz", "**-*", "a*a*a", "*.*.*", "{1:1}_*", "*ñ*"];
+        let filenames = ["my-file-v2.tar.gz", "aaaa", "a.b", "x.y.z.w", "1_2", "-", "ññ", ""];
+        for filter_representation in filters{
+            let filter = RenameFilter::new(filter_representation.to_string(), '*');
+            for filename in filenames{
+                let wildcards = filter.peek_wildcards(filename);
+                assert_eq!(
+                    filter.does_fulfill(filename),
+                    wildcards.len() == filter.wildcard_type.len() && !wildcards.is_empty(),
+                    "{} and {} disagree", filter_representation, filename
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_matcher_does_not_explode() {
+        let filter = RenameFilter::new("*a*a*a*a*a*a*a*a*b".to_string(), '*');
+        assert!(!filter.does_fulfill(&"a".repeat(200)), "Failed starts are not retried.");
+    }
+
+    #[test]
+    fn test_rename_filter_new_typed_wildcards() {
+        let filter = RenameFilter::new("IMG_<d4><d>{sequence}_<a>_<w><?>.jpg".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["IMG_", "", "_", "_", "", ".jpg"]);
+        assert_eq!(filter.get_wildcard_names().get("sequence"), Some(&1), "Typed wildcards can be labeled.");
+        assert_eq!(
+            filter.peek_wildcards("IMG_20240117_Tokio_raw_1x.jpg"),
+            vec![Some("2024".to_string()), Some("0117".to_string()), Some("Tokio".to_string()), Some("raw_1".to_string()), Some("x".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_typed_wildcards_disambiguate() {
+        let filter = RenameFilter::new("IMG_*_*.jpg".to_string(), '*');
+        assert_eq!(filter.peek_wildcards("IMG_trip_day_0042.jpg"), vec![Some("trip".to_string()), Some("day_0042".to_string())]);
+        let filter = RenameFilter::new("IMG_*_<d>.jpg".to_string(), '*');
+        assert_eq!(filter.peek_wildcards("IMG_trip_day_0042.jpg"), vec![Some("trip_day".to_string()), Some("0042".to_string())]);
+        assert!(!filter.does_fulfill("IMG_trip_day_.jpg"), "Typed wildcards match at least one character.");
+        assert!(!filter.does_fulfill("IMG_trip_day_00a2.jpg"), "Typed wildcards only match their characters.");
+    }
+
+    #[test]
+    fn test_typed_wildcards_with_length() {
+        let filter = RenameFilter::new("<d4>-<d2>-<d2>_<?3>.txt".to_string(), '*');
+        assert!(filter.does_fulfill("2024-01-17_abc.txt"));
+        assert!(!filter.does_fulfill("24-01-17_abc.txt"), "Fixed lengths must match exactly.");
+        assert!(!filter.does_fulfill("2024-01-17_ab.txt"));
+        assert!(filter.does_fulfill("2024-01-17_日本語.txt"), "Lengths count characters.");
+        let filter = RenameFilter::new("<ab>_<d0>_<x>".to_string(), '*');
+        assert!(filter.wildcard_type.is_empty(), "Unknown classes and zero lengths are kept as text.");
+        assert!(filter.does_fulfill("<ab>_<d0>_<x>"));
+        let filter = RenameFilter::new("<d99999999999999999999999>_*".to_string(), '*');
+        assert_eq!(filter.wildcard_type.len(), 1, "A length that overflows is kept as text.");
+        assert!(filter.does_fulfill("<d99999999999999999999999>_a"));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let filter = RenameFilter::new("IMG_*.jpg".to_string(), '*');
+        assert!(!filter.does_fulfill("img_Beach.JPG"), "Matching is case sensitive by default.");
+
+        let mut filter = RenameFilter::new("IMG_*.jpg".to_string(), '*').with_ignore_case();
+        assert!(filter.does_fulfill("img_Beach.JPG"));
+        assert!(filter.does_fulfill("Img_Beach.Jpg"));
+        assert_eq!(filter.collect_wildcards("img_Beach.JPG"), vec!["Beach"], "Catched strings keep their case.");
+        assert!(!filter.does_fulfill("img_Beach.png"));
+    }
+
+    #[test]
+    fn test_ignore_case_inline_flag() {
+        let mut filter = RenameFilter::new("(?i)straße_*_ÉTÉ".to_string(), '*');
+        assert_eq!(filter.fixed_str, vec!["straße_", "_ÉTÉ"], "The flag is not part of the filter.");
+        assert_eq!(filter.collect_wildcards("STRAßE_Nr1_été"), vec!["Nr1"]);
+        let filter = RenameFilter::new("x(?i)*".to_string(), '*');
+        assert!(!filter.does_fulfill("X(?I)a"), "The flag only counts at the start of the filter.");
+    }
+
+    #[test]
+    fn test_ignore_case_regex() {
+        let mut filter = RenameFilter::new_regex(r"img_(\d+)\.jpg".to_string()).unwrap().with_ignore_case();
+        assert_eq!(filter.collect_wildcards("IMG_0042.JPG"), vec!["0042"]);
+        let filter = RenameFilter::new_regex(r"(?i)img_(\w+)\.jpg".to_string()).unwrap();
+        assert_eq!(filter.peek_wildcards("IMG_Beach.JPG"), vec![Some("Beach".to_string())]);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+// Files are read in blocks of this size, so big files are never loaded into memory.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm{
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm{
+    pub fn parse(representation: &str) -> Option<Self>{
+        match representation.to_lowercase().as_str(){
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "md5" => Some(HashAlgorithm::Md5),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    // Length of the digest in hexadecimal characters.
+    pub fn hex_length(&self) -> usize{
+        match self{
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Md5 => 32,
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String{
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+enum Hasher{
+    Sha256(Sha256),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher{
+    fn new(algorithm: HashAlgorithm) -> Self{
+        match algorithm{
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, block: &[u8]){
+        match self{
+            Hasher::Sha256(hasher) => hasher.update(block),
+            Hasher::Md5(hasher) => hasher.update(block),
+            Hasher::Blake3(hasher) => {
+                hasher.update(block);
+            },
+        }
+    }
+
+    fn finalize(self) -> String{
+        match self{
+            Hasher::Sha256(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Md5(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+// Digest of the content of the file as lowercase hexadecimal.
+pub fn hash_file(file_path: &Path, algorithm: HashAlgorithm) -> io::Result<String>{
+    let mut file = fs::File::open(file_path)?;
+    let mut buffer = vec![0u8; BLOCK_SIZE];
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        let read = match file.read(&mut buffer){
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    #[test]
+    fn test_parse_algorithm() {
+        assert_eq!(HashAlgorithm::parse("SHA256"), Some(HashAlgorithm::Sha256));
+        assert_eq!(HashAlgorithm::parse("md5"), Some(HashAlgorithm::Md5));
+        assert_eq!(HashAlgorithm::parse("blake3"), Some(HashAlgorithm::Blake3));
+        assert_eq!(HashAlgorithm::parse("crc32"), None);
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let directory = test_directory("hashing");
+        let file_path = directory.join("a.txt");
+        fs::write(&file_path, "abc").unwrap();
+        assert_eq!(hash_file(&file_path, HashAlgorithm::Sha256).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hash_file(&file_path, HashAlgorithm::Md5).unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hash_file(&file_path, HashAlgorithm::Blake3).unwrap(), "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+
+        // more than one block
+        let content = "0123456789".repeat(BLOCK_SIZE / 4);
+        fs::write(&file_path, &content).unwrap();
+        assert_eq!(hash_file(&file_path, HashAlgorithm::Sha256).unwrap(), to_hex(&Sha256::digest(content.as_bytes())));
+        assert_eq!(hash_file(&file_path, HashAlgorithm::Blake3).unwrap().len(), HashAlgorithm::Blake3.hex_length());
+        fs::remove_file(&file_path).unwrap();
+        assert!(hash_file(&file_path, HashAlgorithm::Md5).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -7,7 +7,9 @@ file-renamer v{} - A file renaming utility
     Usage: 
         file-renamer -h|--help
         file-renamer -V|--version
-        file-renamer [-d|--directory <dir>] -f|--filter <filter> -n|--new-name <name> [-r] [-v|--verbose]   [--dry-run] [--wildcard-char <char>] [--position-select-wrapper <chars>]
+        file-renamer [-d|--directory <dir>] --undo [<run-id>] [-v|--verbose] [--dry-run] [--remove-empty-dirs]
+        file-renamer [-d|--directory <dir>] --rules <file> [(-f|--filter <filter>)... (-n|--new-name <name>)...] [options]
+        file-renamer [-d|--directory <dir>] (-f|--filter <filter>)... (-n|--new-name <name>)... [-e|--exclude <filter>]... [-r] [-v|--verbose]   [--dry-run] [--wildcard-char <char>] [--position-select-wrapper <chars>] [--regex] [--atomic] [--on-conflict skip|error|overwrite|suffix] [--suffix-pattern <pattern>] [--sort <keys>] [--normalize nfc|nfd] [-i|--ignore-case] [--min-size <size>] [--max-size <size>] [--modified-after <time>] [--modified-before <time>] [--newer-than <file>] [--type file|dir|symlink] [--dirs|--target files|dirs|all] [--no-create-dirs] [--remove-empty-dirs] [--utc] [--find-duplicates]
 
     Options:
         -h, --help                      Show this help message
@@ -16,20 +18,118 @@ file-renamer v{} - A file renaming utility
         --dry-run                       Run in dry-run mode aka no changes
         -r, --recursive                 Recursively process directories
         -d, --directory                 Base directory to start processing
-        -f, --filter                    Filter to select filenames
-        -n, --new-name                  New name for the files
+        -f, --filter                    Filter to select filenames, can be repeated to
+                                            rename files matching any of them
+        -n, --new-name                  New name for the files, give it once for all the
+                                            filters or once per filter in the same order
+        -e, --exclude                   Filter of files that are never renamed, can be
+                                            repeated
+        --rules <file>                  Read ordered rules (filter, new name and options)
+                                            from a file, see Rules file below
         --wildcard-char                 Wildcard character for renaming
         --position-select-wrapper       Characters used to select wildcard cathched 
                                             strings by position
+        --regex                         Treat the filter as a regular expression, each
+                                            capture group is a wildcard
+
+        --atomic                        Stop at the first failed rename and revert the
+                                            renames already done in the run
+        --on-conflict <policy>          What to do when a new name is already taken or given
+                                            to several files: skip, error (default),
+                                            overwrite or suffix; overwrite cannot be combined
+                                            with --atomic or --find-duplicates
+        --suffix-pattern <pattern>      Suffix added before the extension by the suffix
+                                            policy, # is the number (default " (#)")
+        --sort <keys>                   Order in which matched files are renamed and counters
+                                            applied, comma separated keys: name, natural, mtime,
+                                            ctime, size, ext, capture:<position|name>; prefix a
+                                            key with - to sort it descending, add reverse to flip
+                                            the whole order
+        -i, --ignore-case               Match the fixed parts of the filter regardless of
+                                            case, the catched strings keep their case
+        --normalize <form>              Compare filenames in Unicode normalization form nfc
+                                            or nfd, so precomposed and decomposed accents match
+        --min-size <size>               Only rename files of at least this size, in bytes or
+                                            with a unit: K, M, G, T (powers of 1024) or KB,
+                                            MB, GB, TB (powers of 1000)
+        --max-size <size>               Only rename files of at most this size
+        --modified-after <time>         Only rename files modified after this time: a date
+                                            (2024-01-31), a local date and time
+                                            (2024-01-31 18:30), an RFC 3339 time or a
+                                            duration before now (90s, 30m, 12h, 7d, 2w)
+        --modified-before <time>        Only rename files modified before this time
+        --newer-than <file>             Only rename files modified after the given file
+        --type <type>                   Only rename entries of this type: file, dir or
+                                            symlink; dir implies --dirs
+        --target <target>               Entries matched by the filters: files (default), dirs
+                                            or all
+        --dirs                          Rename directories instead of files, same as
+                                            --target dirs
+        --no-create-dirs                Do not create the missing directories of new names
+                                            that contain a path, such renames fail instead
+        --remove-empty-dirs             Remove the directories left empty by the renames
+                                            or by --undo, the base directory is kept
+        --utc                           Write the time tokens in UTC instead of local time
+        --find-duplicates               Report the matched files with identical content,
+                                            with --new-name rename the duplicates
+        --undo [<run-id>]               Revert the last run (or the given run) recorded in
+                                            the undo journal of the directory
 
     Description:
         This program processes input files and a filter to include files that share specified strings and rename them into a specified name.
         The program can be run in dry-run mode to see what changes would be made without actually renaming any files.
+        All the new names are planned before any file is touched: renames that would overwrite an existing file or give the same name to several files are refused (see --on-conflict), and swaps or chains of renames (a -> b, b -> a) are ordered through temporary names so no file is lost. The plan is printed in verbose and dry-run mode. A file replaced with --on-conflict overwrite is gone for good: neither --atomic nor --undo can bring it back, so overwrite is refused with --atomic.
         The program can also be run recursively to process all files in a directory and its subdirectories.
+        With --dirs or --target all directories are matched by the filters too. Entries are renamed deepest first, so the content of a directory is renamed before the directory itself.
+        Every rename is recorded in a journal file (.file-renamer-journal) in the base directory together with the id of the run, --undo moves the files of a run back to their original names and refuses to touch files that were modified or replaced since. The directories the run created are kept unless --remove-empty-dirs is given with --undo.
         The program supports wildcard characters to match specific parts of filenames and allows for custom selection of matched strings by position.
-        The program also support use of Counters as wildcard characters to rename files in a sequence. (currently there is no way to order the files so there is no guarantie the counters will be applied in the desired order).
+        Several filters can be given, a file is renamed when it matches any of them and none of the --exclude filters. The first filter that matches a file provides its catched strings and counters, and its own new name when one is given per filter.
+        The filter must match the whole filename. Wildcards are lazy: each one takes the shortest text that still lets the rest of the filter match, so "*-*.tar.gz" splits my-file-v2.tar.gz into my and file-v2. Writing the wildcard character twice (**) makes that wildcard greedy, it takes the longest text instead and "**-*.tar.gz" gives my-file and v2.
+        A filter starting with (?i) ignores case like --ignore-case, so "(?i)*.jpg" also matches .JPG and .Jpg files; the wildcards still catch the text as it is written in the filename.
+        Typed wildcards only match some characters: <d> digits, <a> letters, <w> letters, digits and _, each one or more, a number sets an exact length (<d4> is four digits) and <?> is any single character (<?3> any three). They are lazy and can be named and selected like any wildcard, e.g. "IMG_<d4><d2><d2>_*.jpg".
+        With --regex the filter is a regular expression that must match the whole filename, its capture groups are used as the wildcard catched strings and counters ({}) can still be used.
+        A wildcard or counter can be named by writing {{name}} right after it (named groups in --regex mode) and then referenced in the new name as (name) instead of by position.
+        A selected wildcard can be transformed with modifiers written after its position or name, separated by colons and applied in order: upper, lower, title, snake, kebab and camel, e.g. (0:lower:kebab). Numbers can be reformatted with padN (zero padded to N digits), hex and roman, e.g. (0:pad3) turns 7 into 007; text that is not a number is left unchanged.
+        The program also support use of Counters as wildcard characters to rename files in a sequence. Without --sort the counters follow the order in which the directory is read, use --sort to apply them in a predictable order. A third number sets the width the counter is padded to with zeros (at most 255), {{1:1:4}} counts 0001, 0002, ...
+        The size, time and type options are checked together with the filter, a file is only renamed when its name matches and it fulfills all of them. Size and modification time are read through symbolic links. Directories are only matched with --dirs or --target, so --type dir implies --dirs when no target is given and is refused with --target files.
+        Tokens in square brackets add information about the file to the new name: [parent] is the name of its directory, [parent:N] the name of the Nth directory above it ([parent:2] is the directory of the directory), [path] its directory relative to the base directory, [name] the whole original name, [stem] the name without the extension and [ext] the extension without the dot. Brackets that are not a token, like [1080p], are kept as text. [path] is a path: like any / in a new name it moves the file into subdirectories of its own directory, so "[path] - *" renames Lost/Season 02/x to Lost/Season 02/Lost/Season 02 - x. [path:<separator>] joins the directories with the separator instead to use them inside the name, e.g. "[path:_] - *" gives Lost/Season 02/Lost_Season 02 - x.
+        The time tokens [mtime], [ctime] and [atime] are the modification, creation and access time of the file, written as 2024-01-31 or with a strftime format after a colon, e.g. [mtime:%Y-%m-%d_%H%M%S] (%Y year, %m month, %d day, %H hour, %M minute, %S second, %b month name, %A weekday). They are in local time, or in UTC with --utc. The creation time is the modification time on file systems that do not record it.
+        [exif:<tag>] is an EXIF tag of a photo, read from JPEG, TIFF, HEIF, PNG, WebP and the RAW formats based on TIFF (CR2, NEF, ARW, DNG, ...), e.g. [exif:Model], [exif:LensModel] or [exif:DateTimeOriginal]. Dates take a strftime format like the time tokens, [exif:DateTimeOriginal:%Y%m%d], and are written 2024-01-31 without one. A / in the format moves the file into folders, [exif:DateTimeOriginal:%Y/%m]/* sorts photos by year and month.
+        [tag:<name>] is a tag of a music file, read from ID3v2 and ID3v1 in MP3, Vorbis comments in FLAC, Ogg Vorbis and Opus, and the iTunes atoms of M4A. The names title, artist, album, albumartist, track, disc, year, genre and composer work for every format, other Vorbis comments are found by their name. Modifiers can follow the name, e.g. [tag:track:pad2] or [tag:artist:lower].
+        [hash:<algorithm>:<length>] is the digest of the content of the file in lowercase hexadecimal, cut to its first <length> characters, e.g. [hash:sha256:12], [hash:blake3:16] or [hash:md5] for the whole digest. The algorithms are sha256, md5 and blake3; files are read in blocks, so big files are never loaded into memory.
+        With --find-duplicates the matched files are grouped by size, then the files of the same size by their blake3 digest, and every group of identical files is printed with its original first. The original is the first file of the group in the order of the walk (see --sort) and keeps its name. Without --new-name the groups are only reported; with one only the duplicates are renamed, [dup] is the number of the duplicate in its group (1, 2, ...) and [original] the name of the original without its extension. Nothing is ever deleted: --on-conflict overwrite is refused in this mode, empty files and symbolic links are not compared and the exit code is 3 when no duplicates are found.
+        Characters that are not allowed in file names (/ \ < > : " | ? * and control characters) are replaced by _ in the values of EXIF and music tags.
+        A token followed by | and a text, [exif:LensModel|no lens], writes the text when the file has no value for it.
+        A new name can contain a relative path, e.g. "(2)/(1)/photo-(0).png", to move the files into other directories in the same run. The path is relative to the directory of the file and the missing directories are created (see --no-create-dirs). .. can go up, but never out of the base directory, and absolute paths are refused.
         The program can be run in verbose mode to see detailed output of the renaming process.
 
+        Filters, new names and filenames are processed by character, so names in any script can be matched. The same accented letter can be written precomposed or decomposed (usual on macOS), with --normalize the filter and the filenames are compared in one normalization form and the catched strings are taken from the normalized name.
+
+        Long options can also be written as --option=value.
+
+    Rules file:
+        A rules file lists rules in order, the first rule that matches a file renames it and the filters given with -f are tried before the rules of the file.
+        Every rule starts with a [name] header (or [[rule]], so the file can be written as TOML) followed by key = value lines, # starts a comment.
+        Values can be written bare or quoted ("..." with \" \\ \n \t escapes, '...' as is), exclude also takes a list like ["*_thumb.*", "*.tmp"].
+        Keys: filter, new-name (or template), exclude (repeatable), wildcard-char, position-select-wrapper, regex, ignore-case and normalize (nfc, nfd or none); the keys a rule does not set are taken from the command line.
+
+            [photos]
+            filter = IMG_<d>.jpg
+            new-name = photo_*.jpg
+            exclude = *_thumb.*
+
+            [scans]
+            filter = "scan-$.tif"
+            new-name = "page_$.tif"
+            wildcard-char = "$"
+
+    Exit codes:
+        0    All matched files were renamed (or the run was a dry run)
+        1    Nothing could be renamed, or an --atomic run was rolled back
+        2    Usage error: invalid parameters, filter, new name or sort keys
+        3    No file matched the filter (or there was nothing to undo)
+        4    Some files were renamed and others failed
+
     Examples:
         file-renamer -d /path/to/directory -f "*.txt" -n "prefix_*" --wildcard-char * --position-select-wrapper ()
             result:
@@ -49,11 +149,74 @@ file-renamer v{} - A file renaming utility
                 photo-03-04-2022.png -> photo-2022-03-04-png
                 photo-05-06-2024.png -> photo-2024-05-06-png
                 photo-07-08-2024.png -> photo-2024-07-08-png
-    "#, VERSION, "{1:1}");
+        file-renamer -d /photos/month-day-year/ -f "photo-*{{month}}-*{{day}}-*{{year}}.png" -n "photo-(year)-(month)-(day).png"
+            result:
+                photo-01-02-2021.png -> photo-2021-01-02.png
+        file-renamer -d /music -f "* - *.mp3" -n "(0:kebab)_(1:title).mp3"
+            result:
+                The Beatles - let it be.mp3 -> the-beatles_Let It Be.mp3
+        file-renamer -d /camera -f "IMG_*.jpg{{1:1}}" -n "trip_(1)_(0).jpg" --sort mtime,natural
+            result:
+                IMG_0003.jpg (oldest) -> trip_1_0003.jpg
+                IMG_0001.jpg          -> trip_2_0001.jpg
+        file-renamer -d /scans -f "scan*.tif{{1:1:3}}" -n "page_(1)_(0:pad4).tif" --sort natural
+            result:
+                scan7.tif -> page_001_0007.tif
+                scan12.tif -> page_002_0012.tif
+        file-renamer -d /camera -f "IMG_<d4>{{year}}<d2>{{month}}<d2>_<d>.jpg" -n "(year)-(month) (3).jpg"
+            result:
+                IMG_20240117_0042.jpg -> 2024-01 0042.jpg
+        file-renamer -d /images -f "*.jpg" -n "photo_*.jpg" -f "*.png" -n "image_*.png" -e "*_thumb.*"
+            result:
+                beach.jpg -> photo_beach.jpg
+                logo.png -> image_logo.png
+                beach_thumb.jpg (excluded)
+        file-renamer -d /photos -f "<d4>{{year}}-<d2>{{month}}-<d2>{{day}} *" -n "(3) ((year)-(month)-(day))" --dirs
+            result:
+                2021-01-02 Trip/ -> Trip (2021-01-02)/
+        file-renamer -d /photos -f "photo-*-*-*.png" -n "(2)/(0)/photo-(1).png" --remove-empty-dirs
+            result:
+                photo-01-02-2021.png -> 2021/01/photo-02.png
+                photo-03-04-2022.png -> 2022/03/photo-04.png
+        file-renamer -d /shows -r -f "Episode <d>.mkv" -n "[parent:2] - [parent] - E(0:pad2).[ext]"
+            result:
+                Lost/Season 02/Episode 5.mkv -> Lost/Season 02/Lost - Season 02 - E05.mkv
+        file-renamer -d /camera -f "IMG_*.jpg" -n "[mtime:%Y]/[mtime:%Y-%m-%d_%H%M%S]_*.jpg"
+            result:
+                IMG_0042.jpg -> 2024/2024-01-31_183000_0042.jpg
+        file-renamer -d /camera -f "*.jpg" -n "[exif:DateTimeOriginal:%Y%m%d_%H%M%S|undated]_[exif:Model|unknown].jpg"
+            result:
+                IMG_0042.jpg -> 20240131_183000_Canon EOS R6.jpg
+                scan.jpg -> undated_unknown.jpg
+        file-renamer -d /music -r -f "*.<w>" -n "[tag:artist] - [tag:album] - [tag:track:pad2] [tag:title|untitled].[ext]"
+            result:
+                01.mp3 -> The Beatles - Abbey Road - 07 Here Comes the Sun.mp3
+        file-renamer -d /downloads -f "*.<w>{{ext}}" -n "[hash:sha256:12].(ext)"
+            result:
+                report (1).pdf -> 3f2a9c01b7de.pdf
+                report.pdf -> 3f2a9c01b7de.pdf (same content, refused as a conflict unless --on-conflict is given)
+        file-renamer -d /photos -f "*.<w>{{ext}}" -n "[original].dup[dup].(ext)" --find-duplicates --sort name
+            result:
+                IMG_1.jpg (kept)
+                IMG_1_copy.jpg -> IMG_1.dup1.jpg
+                IMG_1_copy2.jpg -> IMG_1.dup2.jpg
+        file-renamer -d /recordings -f "rec_*.wav" -n "archive_*.wav" --modified-before 7d --min-size 1G
+            result:
+                rec_monday.wav (3 GB, two weeks old) -> archive_monday.wav
+                rec_today.wav (not renamed, too new)
+        file-renamer -d /photos -f "IMG_(\d+)\.(jpg|jpeg)" -n "photo_(0).(1)" --regex
+            result:
+                IMG_0042.jpeg -> photo_0042.jpeg
+                IMG_0043.jpg -> photo_0043.jpg
+    "#, VERSION, "{start:inc}", "{1:1}");
     help_string.to_string()
 }
 
 pub fn get_version() -> String {
     let version_string = format!("file-renamer v{}", VERSION);
     version_string.to_string()
-}
\ No newline at end of file
+}
+
+pub fn get_usage_hint() -> String {
+    "Usage: file-renamer [-d <dir>] -f <filter> -n <name> [options]\nRun 'file-renamer --help' for more information.".to_string()
+}
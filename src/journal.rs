@@ -0,0 +1,317 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{self, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::planner::RenameOperation;
+
+// Stored in the target directory, the walk never offers it to the filters.
+pub const JOURNAL_FILE_NAME: &str = ".file-renamer-journal";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry{
+    pub RunId: String,
+    pub Timestamp: u64,
+    pub From: PathBuf,
+    pub To: PathBuf,
+    // size and modification time of the renamed file, used to detect later changes
+    pub Size: u64,
+    pub Modified: u128,
+}
+
+pub struct Journal{
+    path: PathBuf,
+    run_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UndoError{
+    TargetChanged(PathBuf),
+    TargetMissing(PathBuf),
+    SourceTaken(PathBuf),
+    Io(String),
+}
+
+impl fmt::Display for UndoError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            UndoError::TargetChanged(target) => write!(f, "{} has changed since it was renamed.", target.display()),
+            UndoError::TargetMissing(target) => write!(f, "{} no longer exists.", target.display()),
+            UndoError::SourceTaken(source) => write!(f, "{} already exists.", source.display()),
+            UndoError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub fn journal_path(directory: &Path) -> PathBuf{
+    directory.join(JOURNAL_FILE_NAME)
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64{
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+fn file_signature(file_path: &Path) -> io::Result<(u64, u128)>{
+    let metadata = fs::symlink_metadata(file_path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+    Ok((metadata.len(), modified))
+}
+
+// Fields are tab separated, so tabs, newlines and backslashes in paths are escaped.
+fn escape_field(field: &str) -> String{
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape_field(field: &str) -> String{
+    let mut result = String::new();
+    let mut characters = field.chars();
+    while let Some(character) = characters.next(){
+        if character != '\\'{
+            result.push(character);
+            continue;
+        }
+        match characters.next(){
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+impl JournalEntry{
+    fn to_line(&self) -> String{
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            escape_field(&self.RunId),
+            self.Timestamp,
+            self.Size,
+            self.Modified,
+            escape_field(&self.From.to_string_lossy()),
+            escape_field(&self.To.to_string_lossy()),
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self>{
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 6{
+            return None;
+        }
+        Some(JournalEntry{
+            RunId: unescape_field(fields[0]),
+            Timestamp: fields[1].parse().ok()?,
+            Size: fields[2].parse().ok()?,
+            Modified: fields[3].parse().ok()?,
+            From: PathBuf::from(unescape_field(fields[4])),
+            To: PathBuf::from(unescape_field(fields[5])),
+        })
+    }
+}
+
+impl Journal{
+    pub fn new(directory: &Path) -> Self{
+        let run_id = format!("{}-{}", seconds_since_epoch(SystemTime::now()), std::process::id());
+        Journal{
+            path: journal_path(directory),
+            run_id,
+        }
+    }
+
+    pub fn get_run_id(&self) -> &str{
+        &self.run_id
+    }
+
+    // Appends a rename that already happened, each entry is flushed so a crash keeps the journal usable.
+    pub fn record(&mut self, operation: &RenameOperation) -> io::Result<()>{
+        let (size, modified) = file_signature(&operation.To)?;
+        let entry = JournalEntry{
+            RunId: self.run_id.clone(),
+            Timestamp: seconds_since_epoch(SystemTime::now()),
+            From: path::absolute(&operation.From)?,
+            To: path::absolute(&operation.To)?,
+            Size: size,
+            Modified: modified,
+        };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(entry.to_line().as_bytes())?;
+        file.flush()
+    }
+
+    // Removes entries of this run for renames that were reverted, e.g. by an atomic rollback.
+    pub fn forget(&self, operations: &[RenameOperation]) -> io::Result<()>{
+        let directory = self.path.parent().unwrap_or(Path::new(""));
+        let mut reverted = vec![];
+        for operation in operations{
+            reverted.push((path::absolute(&operation.From)?, path::absolute(&operation.To)?));
+        }
+        let entries: Vec<JournalEntry> = read_journal(directory)?
+            .into_iter()
+            .filter(|entry| entry.RunId != self.run_id || !reverted.contains(&(entry.From.clone(), entry.To.clone())))
+            .collect();
+        write_journal(directory, &entries)
+    }
+}
+
+pub fn read_journal(directory: &Path) -> io::Result<Vec<JournalEntry>>{
+    let content = match fs::read_to_string(journal_path(directory)){
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+    Ok(content.lines().filter_map(JournalEntry::from_line).collect())
+}
+
+pub fn write_journal(directory: &Path, entries: &[JournalEntry]) -> io::Result<()>{
+    let file_path = journal_path(directory);
+    if entries.is_empty(){
+        return match fs::remove_file(file_path){
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        };
+    }
+    let content: String = entries.iter().map(|entry| entry.to_line()).collect();
+    fs::write(file_path, content)
+}
+
+// The entries of the chosen run (the last one when None) in the order they were renamed.
+pub fn select_run(entries: &[JournalEntry], run_id: Option<&str>) -> Vec<JournalEntry>{
+    let run_id = match run_id{
+        Some(run_id) => run_id.to_string(),
+        None => match entries.last(){
+            Some(entry) => entry.RunId.clone(),
+            None => return vec![],
+        },
+    };
+    entries.iter().filter(|entry| entry.RunId == run_id).cloned().collect()
+}
+
+// Moves the file back to its original name, refusing if it was modified or replaced since.
+pub fn undo_entry(entry: &JournalEntry) -> Result<(), UndoError>{
+    match file_signature(&entry.To){
+        Ok(signature) if signature == (entry.Size, entry.Modified) => {},
+        Ok(_) => return Err(UndoError::TargetChanged(entry.To.clone())),
+        Err(_) => return Err(UndoError::TargetMissing(entry.To.clone())),
+    }
+    if fs::symlink_metadata(&entry.From).is_ok(){
+        return Err(UndoError::SourceTaken(entry.From.clone()));
+    }
+    // the directory the file came from may have been removed once it was empty
+    if let Some(parent) = entry.From.parent().filter(|parent| !parent.as_os_str().is_empty()){
+        fs::create_dir_all(parent).map_err(|error| UndoError::Io(error.to_string()))?;
+    }
+    fs::rename(&entry.To, &entry.From).map_err(|error| UndoError::Io(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    fn rename_and_record(journal: &mut Journal, from: &Path, to: &Path){
+        fs::rename(from, to).unwrap();
+        journal.record(&RenameOperation{ From: from.to_path_buf(), To: to.to_path_buf() }).unwrap();
+    }
+
+    #[test]
+    fn test_entry_line_round_trip() {
+        let entry = JournalEntry{
+            RunId: "1-2".to_string(),
+            Timestamp: 3,
+            From: PathBuf::from("/dir/with\ttab\\and\nnewline"),
+            To: PathBuf::from("/dir/new"),
+            Size: 4,
+            Modified: 5,
+        };
+        let line = entry.to_line();
+        assert_eq!(line.matches('\n').count(), 1, "An entry is a single line.");
+        assert_eq!(JournalEntry::from_line(line.trim_end_matches('\n')), Some(entry));
+    }
+
+    #[test]
+    fn test_select_run() {
+        let entry = |run_id: &str, name: &str| JournalEntry{
+            RunId: run_id.to_string(),
+            Timestamp: 0,
+            From: PathBuf::from(name),
+            To: PathBuf::from(name),
+            Size: 0,
+            Modified: 0,
+        };
+        let entries = vec![entry("a", "1"), entry("b", "2"), entry("b", "3")];
+        assert_eq!(select_run(&entries, None), vec![entry("b", "2"), entry("b", "3")], "The last run is selected by default.");
+        assert_eq!(select_run(&entries, Some("a")), vec![entry("a", "1")]);
+        assert!(select_run(&entries, Some("c")).is_empty());
+    }
+
+    #[test]
+    fn test_record_and_undo() {
+        let directory = test_directory("journal-undo");
+        fs::write(directory.join("a"), "a").unwrap();
+        let mut journal = Journal::new(&directory);
+        rename_and_record(&mut journal, &directory.join("a"), &directory.join("b"));
+
+        let entries = read_journal(&directory).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].RunId, journal.get_run_id());
+        assert_eq!(undo_entry(&entries[0]), Ok(()));
+        assert_eq!(fs::read_to_string(directory.join("a")).unwrap(), "a", "The file is back to its original name.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_undo_recreates_removed_directory() {
+        let directory = test_directory("journal-undo-directory");
+        fs::create_dir(directory.join("old")).unwrap();
+        fs::write(directory.join("old").join("a"), "a").unwrap();
+        let mut journal = Journal::new(&directory);
+        rename_and_record(&mut journal, &directory.join("old").join("a"), &directory.join("a"));
+        fs::remove_dir(directory.join("old")).unwrap();
+
+        let entries = read_journal(&directory).unwrap();
+        assert_eq!(undo_entry(&entries[0]), Ok(()));
+        assert_eq!(fs::read_to_string(directory.join("old").join("a")).unwrap(), "a", "The emptied directory is created again.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_undo_refuses_changed_target() {
+        let directory = test_directory("journal-changed");
+        fs::write(directory.join("a"), "a").unwrap();
+        let mut journal = Journal::new(&directory);
+        rename_and_record(&mut journal, &directory.join("a"), &directory.join("b"));
+        fs::write(directory.join("b"), "modified").unwrap();
+
+        let entries = read_journal(&directory).unwrap();
+        assert_eq!(undo_entry(&entries[0]), Err(UndoError::TargetChanged(path::absolute(directory.join("b")).unwrap())));
+        assert!(!directory.join("a").exists(), "A changed file is not moved back.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_write_journal_removes_empty_journal() {
+        let directory = test_directory("journal-empty");
+        fs::write(journal_path(&directory), "").unwrap();
+        write_journal(&directory, &[]).unwrap();
+        assert!(!journal_path(&directory).exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_forget_reverted_renames() {
+        let directory = test_directory("journal-forget");
+        fs::write(directory.join("a"), "a").unwrap();
+        fs::write(directory.join("b"), "b").unwrap();
+        let mut journal = Journal::new(&directory);
+        rename_and_record(&mut journal, &directory.join("a"), &directory.join("x"));
+        rename_and_record(&mut journal, &directory.join("b"), &directory.join("y"));
+        journal.forget(&[RenameOperation{ From: directory.join("b"), To: directory.join("y") }]).unwrap();
+
+        let entries = read_journal(&directory).unwrap();
+        assert_eq!(entries.len(), 1, "Only the reverted rename is forgotten.");
+        assert_eq!(entries[0].To, path::absolute(directory.join("x")).unwrap());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
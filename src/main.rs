@@ -1,10 +1,24 @@
+#![allow(non_snake_case)]
+
 mod cli;
 mod filters;
 mod renamer;
 mod help;
+mod planner;
+mod journal;
+mod sorter;
+mod modifiers;
+mod rules;
+mod metadata;
+mod tokens;
+mod audio;
+mod hashing;
+mod duplicates;
+#[cfg(test)]
+mod test_support;
 
+use std::collections::HashMap;
 use std::env;
-use std::fs;
 use std::path;
 
 use colored::Colorize;
@@ -12,7 +26,10 @@ use colored::Colorize;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let cli = cli::CliParameters::new(args);
+    let cli = match cli::CliParameters::new(args) {
+        Ok(cli) => cli,
+        Err(e) => exit_with_usage_error(&e.to_string()),
+    };
     if cli.Help {
         println!("{}", help::get_help_string());
         return;
@@ -22,16 +39,49 @@ fn main() {
         println!("{}", help::get_version());
         return;
     }
+
+    if cli.Undo {
+        undo(&cli);
+        return;
+    }
     
-    let mut filter = filters::RenameFilter::new(
-        cli.Filter.clone(), 
-        cli.WildcardChar.clone()
-    );
-    let renamer = renamer::Renamer::new(
-        cli.Output.clone(), 
-        cli.WildcardChar.clone(), 
-        cli.PositionSelectWrapper.clone()
-    );
+    let mut rules = cli_rules(&cli);
+    if let Some(rules_file) = &cli.Rules {
+        match rules::read_rules_file(rules_file, &default_rule(&cli)) {
+            Ok(file_rules) => rules.extend(file_rules),
+            Err(e) => exit_with_usage_error(&e.to_string()),
+        }
+    }
+    if rules.is_empty() {
+        exit_with_usage_error(&cli::CliError::MissingParameter("--filter".to_string()).to_string());
+    }
+    // --exclude applies to every rule
+    let mut rule_set = match rules::RuleSet::new(&rules).and_then(|rule_set| rule_set.with_excludes(&cli.Excludes, &default_rule(&cli))) {
+        Ok(rule_set) => rule_set,
+        Err(e) => exit_with_usage_error(&e.to_string()),
+    };
+
+    let mut metadata_filter = metadata::MetadataFilter {
+        MinSize: cli.MinSize,
+        MaxSize: cli.MaxSize,
+        ModifiedAfter: cli.ModifiedAfter,
+        ModifiedBefore: cli.ModifiedBefore,
+        Type: cli.Type,
+    };
+    if let Some(reference) = &cli.NewerThan {
+        let reference_time = match std::fs::metadata(reference).and_then(|metadata| metadata.modified()) {
+            Ok(reference_time) => reference_time,
+            Err(e) => exit_with_usage_error(&format!("Could not read the modification time of '{}': {}", reference.display(), e)),
+        };
+        metadata_filter.ModifiedAfter = Some(metadata_filter.ModifiedAfter.map_or(reference_time, |after| after.max(reference_time)));
+    }
+
+    let sorter = cli.Sort.as_ref().map(|sort| {
+        match sorter::FileSorter::new(sort, &rule_set.get_wildcard_names()) {
+            Ok(sorter) => sorter,
+            Err(e) => exit_with_usage_error(&e),
+        }
+    });
 
     if cli.DryRun {
         println!("Running in dry-run mode. No changes will be made.");
@@ -41,38 +91,45 @@ fn main() {
         println!("Verbose mode enabled.");
     }
 
+    let mut matched_files: Vec<path::PathBuf> = vec![];
     let mut directories:Vec<std::path::PathBuf> = vec![];
     directories.push(cli.Directory.clone()); 
 
-    while !directories.is_empty(){
-        let current_directory = directories.pop().unwrap();
-        let abs_path = path::absolute(&current_directory).unwrap();
-        println!("{}", &abs_path.to_str().unwrap().on_blue());
-        for files in std::fs::read_dir(current_directory).unwrap() {
+    while let Some(current_directory) = directories.pop(){
+        let abs_path = path::absolute(&current_directory).unwrap_or(current_directory.clone());
+        println!("{}", abs_path.display().to_string().on_blue());
+        let entries = match std::fs::read_dir(&current_directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("{}", format!("Could not read {}: {}", abs_path.display(), e).red());
+                continue;
+            }
+        };
+        for files in entries {
             match files {
                 Ok(file)=>{
                     let file_path = file.path();
-                    if file_path.is_dir() {
-                        if cli.Recursive{
-                            directories.push(file_path.clone());
-                        }
+                    let is_dir = file_path.is_dir();
+                    if is_dir && cli.Recursive {
+                        directories.push(file_path.clone());
+                    }
+                    if !cli.Target.includes(is_dir) {
                         continue;
                     }
-                    let file_name_str = file_path.file_name().unwrap().to_str().unwrap();
-                    if !filter.does_fulfill(&file_name_str){
+                    let Some(file_name_str) = file_path.file_name().and_then(|name| name.to_str()) else {
+                        eprintln!("{}", format!("Skipping {}: the name is not valid UTF-8.", file_path.display()).yellow());
+                        continue;
+                    };
+                    if file_name_str == journal::JOURNAL_FILE_NAME {
                         continue;
                     }
-                    let wildcard_catched = filter.collect_wildcards(&file_name_str);
-                    let new_filename = renamer.generate_rename_filename(&wildcard_catched);
-                    if cli.Verbose{
-                        println!("\t{} -> {}", file_name_str.on_red(), new_filename.on_green());
+                    if rule_set.find(file_name_str).is_none(){
+                        continue;
                     }
-                    if cli.DryRun{
+                    if !metadata_filter.does_fulfill(&file_path){
                         continue;
                     }
-                    let full_old_path = file_path.clone();
-                    let full_new_path = file_path.parent().unwrap().join(new_filename);
-                    _ = fs::rename(full_old_path, full_new_path); 
+                    matched_files.push(file_path.clone());
                 },
                 Err(e)=>{
                     println!("Error reading directory: {}", e);
@@ -81,4 +138,279 @@ fn main() {
             }
         }
     }
+
+    if matched_files.is_empty() {
+        println!("No files matched the filter.");
+        std::process::exit(cli::EXIT_NOTHING_MATCHED);
+    }
+
+    // counters are only assigned once the files are in their final order
+    if let Some(sorter) = &sorter {
+        sorter.sort(&mut matched_files, |file_name| rule_set.find(file_name).map(|index| rule_set.get_filter(index)));
+    }
+    // the first file of each group is the original and keeps its name, the others are only renamed with a new name
+    let mut duplicate_of: HashMap<path::PathBuf, (usize, path::PathBuf)> = HashMap::new();
+    if cli.FindDuplicates {
+        let groups = report_duplicates(&cli, &matched_files);
+        if groups.is_empty() {
+            println!("No duplicates found.");
+            std::process::exit(cli::EXIT_NOTHING_MATCHED);
+        }
+        if cli.Outputs.is_empty() && cli.Rules.is_none() {
+            return;
+        }
+        for group in groups {
+            for (number, file_path) in group.Files.iter().enumerate().skip(1) {
+                duplicate_of.insert(file_path.clone(), (number, group.Files[0].clone()));
+            }
+        }
+        matched_files.retain(|file_path| duplicate_of.contains_key(file_path));
+    }
+    let mut renames: Vec<(path::PathBuf, path::PathBuf)> = vec![];
+    let mut target_errors = vec![];
+    for file_path in matched_files {
+        // only files with a valid UTF-8 name were matched
+        let file_name_str = file_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        // the first rule that matches gives the captures and the new name
+        let Some(index) = rule_set.find(file_name_str) else {
+            continue;
+        };
+        let mut context = tokens::FileContext::new(&file_path, &cli.Directory).with_utc(cli.Utc);
+        if let Some((number, original)) = duplicate_of.get(&file_path) {
+            context = context.with_duplicate(*number, original);
+        }
+        let new_filename = rule_set.rename(index, file_name_str, &context);
+        // the new name can move the file into other directories below the base directory
+        match planner::target_path(&cli.Directory, &file_path, &new_filename) {
+            Ok(full_new_path) => renames.push((file_path, full_new_path)),
+            Err(e) => target_errors.push(e),
+        }
+    }
+    if !target_errors.is_empty() {
+        for error in target_errors {
+            eprintln!("{}", error.to_string().red());
+        }
+        std::process::exit(cli::EXIT_FAILURE);
+    }
+
+    let plan = match planner::RenamePlan::new(renames, &cli.OnConflict, &cli.SuffixPattern) {
+        Ok(plan) => plan.with_create_directories(cli.CreateDirs),
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error.to_string().red());
+            }
+            std::process::exit(cli::EXIT_FAILURE);
+        }
+    };
+
+    if cli.Verbose {
+        println!("Rename plan:");
+        for operation in &plan.Operations {
+            println!(
+                "\t{} -> {}",
+                display_path(&operation.From, &cli.Directory).on_red(),
+                display_path(&operation.To, &cli.Directory).on_green()
+            );
+        }
+        for (from, to) in &plan.Skipped {
+            println!(
+                "\t{} -> {} {}",
+                display_path(from, &cli.Directory),
+                display_path(to, &cli.Directory),
+                "(skipped, name already taken)".yellow()
+            );
+        }
+    }
+
+    if cli.DryRun {
+        return;
+    }
+
+    let mut journal = journal::Journal::new(&cli.Directory);
+    let mut renamed = 0;
+    let on_renamed = |operation: &planner::RenameOperation| {
+        renamed += 1;
+        if let Err(e) = journal.record(operation) {
+            eprintln!("{}", format!("Could not write the undo journal for {}: {}", operation.From.display(), e).red());
+        }
+    };
+
+    if cli.Atomic {
+        if let Err(report) = plan.apply_atomic(on_renamed) {
+            report_failure(&report.Failure);
+            for operation in &report.RolledBack {
+                eprintln!("{}", format!("Rolled back {} -> {}", operation.To.display(), operation.From.display()).yellow());
+            }
+            for failure in &report.RollbackFailures {
+                eprintln!(
+                    "{}",
+                    format!("Could not roll back {} -> {}: {}", failure.Operation.To.display(), failure.Operation.From.display(), failure.Error).red()
+                );
+            }
+            if let Err(e) = journal.forget(&report.RolledBack) {
+                eprintln!("{}", format!("Could not update the undo journal: {}", e).red());
+            }
+            // files that could not be rolled back keep their new name
+            remove_empty_directories(&cli, &plan);
+            if report.RollbackFailures.is_empty() {
+                std::process::exit(cli::EXIT_FAILURE);
+            }
+            std::process::exit(cli::EXIT_PARTIAL_FAILURE);
+        }
+        if renamed > 0 {
+            println!("Run id: {} (use --undo to revert it)", journal.get_run_id());
+        }
+        remove_empty_directories(&cli, &plan);
+        return;
+    }
+
+    let failures = plan.apply(on_renamed);
+    for failure in &failures {
+        report_failure(failure);
+    }
+    remove_empty_directories(&cli, &plan);
+    if renamed > 0 {
+        println!("Run id: {} (use --undo to revert it)", journal.get_run_id());
+    }
+    if !failures.is_empty() {
+        std::process::exit(if renamed > 0 { cli::EXIT_PARTIAL_FAILURE } else { cli::EXIT_FAILURE });
+    }
+}
+
+// Options of the command line, used by the rules that do not set their own.
+fn default_rule(cli: &cli::CliParameters) -> rules::Rule {
+    rules::Rule {
+        Name: String::new(),
+        Filter: String::new(),
+        NewName: String::new(),
+        WildcardChar: cli.WildcardChar,
+        PositionSelectWrapper: cli.PositionSelectWrapper,
+        Regex: cli.Regex,
+        IgnoreCase: cli.IgnoreCase,
+        Normalize: cli.Normalize,
+        Excludes: vec![],
+    }
+}
+
+// Each --filter is paired with its own new name, or all share the only one given. They are tried before the rules file.
+// --find-duplicates only reports the duplicates when no new name is given.
+fn cli_rules(cli: &cli::CliParameters) -> Vec<rules::Rule> {
+    if !cli.Filters.is_empty() && cli.Outputs.is_empty() && !cli.FindDuplicates {
+        exit_with_usage_error(&cli::CliError::MissingParameter("--new-name".to_string()).to_string());
+    }
+    cli.Filters.iter().enumerate()
+        .map(|(index, filter)| rules::Rule {
+            Name: filter.clone(),
+            Filter: filter.clone(),
+            NewName: cli.Outputs.get(index).or(cli.Outputs.first()).cloned().unwrap_or_default(),
+            ..default_rule(cli)
+        })
+        .collect()
+}
+
+fn exit_with_usage_error(message: &str) -> ! {
+    eprintln!("{}", message.red());
+    eprintln!("{}", help::get_usage_hint());
+    std::process::exit(cli::EXIT_USAGE_ERROR);
+}
+
+fn report_failure(failure: &planner::ApplyFailure) {
+    eprintln!(
+        "{}",
+        format!("Could not rename {} -> {}: {}", failure.Operation.From.display(), failure.Operation.To.display(), failure.Error).red()
+    );
+}
+
+fn remove_empty_directories(cli: &cli::CliParameters, plan: &planner::RenamePlan) {
+    if !cli.RemoveEmptyDirs {
+        return;
+    }
+    for directory in plan.remove_empty_directories(&cli.Directory) {
+        if cli.Verbose {
+            println!("\tRemoved empty directory {}", display_path(&directory, &cli.Directory));
+        }
+    }
+}
+
+// Moves the files of the last (or chosen) run back to their original names, newest rename first.
+fn undo(cli: &cli::CliParameters) {
+    let entries = match journal::read_journal(&cli.Directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", format!("Could not read the undo journal: {}", e).red());
+            std::process::exit(cli::EXIT_FAILURE);
+        }
+    };
+    let run = journal::select_run(&entries, cli.UndoRunId.as_deref());
+    if run.is_empty() {
+        match &cli.UndoRunId {
+            Some(run_id) => eprintln!("{}", format!("Run {} is not in the undo journal.", run_id).red()),
+            None => println!("Nothing to undo."),
+        }
+        std::process::exit(cli::EXIT_NOTHING_MATCHED);
+    }
+    println!("Undoing run {}", run[0].RunId);
+
+    let mut undone = vec![];
+    let mut failed = false;
+    for entry in run.iter().rev() {
+        if cli.Verbose {
+            println!(
+                "\t{} -> {}",
+                display_path(&entry.To, &cli.Directory).on_red(),
+                display_path(&entry.From, &cli.Directory).on_green()
+            );
+        }
+        if cli.DryRun {
+            continue;
+        }
+        match journal::undo_entry(entry) {
+            Ok(()) => undone.push(entry.clone()),
+            Err(e) => {
+                failed = true;
+                eprintln!("{}", format!("Could not undo {} -> {}: {}", entry.From.display(), entry.To.display(), e).red());
+            }
+        }
+    }
+
+    if cli.DryRun {
+        return;
+    }
+    let remaining: Vec<journal::JournalEntry> = entries.into_iter().filter(|entry| !undone.contains(entry)).collect();
+    if let Err(e) = journal::write_journal(&cli.Directory, &remaining) {
+        eprintln!("{}", format!("Could not update the undo journal: {}", e).red());
+        failed = true;
+    }
+    // the directories a run created are kept unless asked, they may have been empty before the run too
+    if cli.RemoveEmptyDirs {
+        let moved_paths: Vec<&path::Path> = undone.iter().map(|entry| entry.To.as_path()).collect();
+        for directory in planner::remove_emptied_directories(&moved_paths, &cli.Directory) {
+            if cli.Verbose {
+                println!("\tRemoved empty directory {}", display_path(&directory, &cli.Directory));
+            }
+        }
+    }
+    if failed {
+        std::process::exit(if undone.is_empty() { cli::EXIT_FAILURE } else { cli::EXIT_PARTIAL_FAILURE });
+    }
+}
+
+// Prints every group of identical files, the original first. Files that could not be read are left out of the groups.
+fn report_duplicates(cli: &cli::CliParameters, files: &[path::PathBuf]) -> Vec<duplicates::DuplicateGroup> {
+    let (groups, errors) = duplicates::find_duplicates(files);
+    for (file_path, e) in &errors {
+        eprintln!("{}", format!("Could not read {}: {}", file_path.display(), e).yellow());
+    }
+    for group in &groups {
+        println!("{} ({} bytes)", display_path(&group.Files[0], &cli.Directory).on_green(), group.Size);
+        for duplicate in &group.Files[1..] {
+            println!("\t{}", display_path(duplicate, &cli.Directory));
+        }
+    }
+    groups
+}
+
+// Paths in the plan are shown relative to the directory given by the user.
+fn display_path(file_path: &path::Path, directory: &path::Path) -> String {
+    file_path.strip_prefix(directory).unwrap_or(file_path).display().to_string()
 }
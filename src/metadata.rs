@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType{
+    File,
+    Dir,
+    Symlink,
+}
+
+impl FileType{
+    pub fn parse(representation: &str) -> Option<Self>{
+        match representation{
+            "file" | "f" => Some(FileType::File),
+            "dir" | "directory" | "d" => Some(FileType::Dir),
+            "symlink" | "link" | "l" => Some(FileType::Symlink),
+            _ => None,
+        }
+    }
+}
+
+// Conditions on the metadata of a file, checked after its name matched a filter. Every condition that is set must hold.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MetadataFilter{
+    pub MinSize: Option<u64>,
+    pub MaxSize: Option<u64>,
+    pub ModifiedAfter: Option<SystemTime>,
+    pub ModifiedBefore: Option<SystemTime>,
+    pub Type: Option<FileType>,
+}
+
+// Errors are the reason the value is refused, written after "The value of '--option'".
+// Sizes are bytes with an optional unit: K, M, G, T (and KiB, MiB, ...) are powers of 1024, KB, MB, GB, TB powers of 1000.
+pub fn parse_size(representation: &str) -> Result<u64, String>{
+    let representation = representation.trim();
+    let digits_end = representation.find(|character: char| !character.is_ascii_digit() && character != '.').unwrap_or(representation.len());
+    let (number, unit) = representation.split_at(digits_end);
+    let number: f64 = number.parse().map_err(|_| "must be a size like 500, 500K, 2MiB or 3GB".to_string())?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str(){
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(format!("must be a size like 500, 500K, 2MiB or 3GB, '{}' is not a unit", unit.trim())),
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+// Times are a date (2024-01-31), a date and time in local time (2024-01-31 18:30 or 2024-01-31T18:30:00),
+// an RFC 3339 time with an offset (2024-01-31T18:30:00Z) or a duration before now (90s, 30m, 12h, 7d, 2w).
+pub fn parse_time(representation: &str, now: SystemTime) -> Result<SystemTime, String>{
+    let representation = representation.trim();
+    if let Some(ago) = parse_duration(representation){
+        return now.checked_sub(ago).ok_or("must be a duration that does not go back before 1970".to_string());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(representation){
+        return Ok(time.into());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(representation, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(representation, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)));
+    match naive.and_then(|naive| Local.from_local_datetime(&naive).earliest()){
+        Some(time) => Ok(time.into()),
+        None => Err("must be a date, a date and time or a duration like 7d".to_string()),
+    }
+}
+
+fn parse_duration(representation: &str) -> Option<Duration>{
+    let unit = representation.chars().last()?;
+    let seconds: u64 = match unit{
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let number: u64 = representation[..representation.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(seconds)?))
+}
+
+impl MetadataFilter{
+    pub fn is_empty(&self) -> bool{
+        *self == MetadataFilter::default()
+    }
+
+    // Size and modification time are read through symlinks, the type is the one of the entry itself.
+    pub fn does_fulfill(&self, file_path: &Path) -> bool{
+        if self.is_empty(){
+            return true;
+        }
+        let Ok(link_metadata) = fs::symlink_metadata(file_path) else {
+            return false;
+        };
+        if let Some(file_type) = self.Type{
+            let matches = match file_type{
+                FileType::File => link_metadata.is_file(),
+                FileType::Dir => link_metadata.is_dir(),
+                FileType::Symlink => link_metadata.file_type().is_symlink(),
+            };
+            if !matches{
+                return false;
+            }
+        }
+        // a broken symlink only has its own metadata
+        let metadata = fs::metadata(file_path).unwrap_or(link_metadata);
+        let size = metadata.len();
+        if self.MinSize.is_some_and(|min_size| size < min_size) || self.MaxSize.is_some_and(|max_size| size > max_size){
+            return false;
+        }
+        if self.ModifiedAfter.is_none() && self.ModifiedBefore.is_none(){
+            return true;
+        }
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+        !(self.ModifiedAfter.is_some_and(|after| modified <= after) || self.ModifiedBefore.is_some_and(|before| modified >= before))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("2K"), Ok(2048));
+        assert_eq!(parse_size("1.5MiB"), Ok(1_572_864));
+        assert_eq!(parse_size("1 GB"), Ok(1_000_000_000));
+        assert_eq!(parse_size("1g"), Ok(1 << 30));
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("10X").is_err());
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(parse_time("7d", now), Ok(now - Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_time("90s", now), Ok(now - Duration::from_secs(90)));
+        assert_eq!(parse_time("2024-01-31T18:30:00Z", now), Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(1_706_725_800)));
+        let local_date = parse_time("2024-01-31", now).unwrap();
+        let local_time = parse_time("2024-01-31 18:30", now).unwrap();
+        assert_eq!(local_time.duration_since(local_date).ok(), Some(Duration::from_secs(18 * 60 * 60 + 30 * 60)), "Dates are midnight local time.");
+        assert!(parse_time("yesterday", now).is_err());
+        assert!(parse_time("2024-13-01", now).is_err());
+    }
+
+    #[test]
+    fn test_metadata_filter_size_and_type() {
+        let directory = test_directory("metadata-size");
+        fs::write(directory.join("small"), "a").unwrap();
+        fs::write(directory.join("large"), "a".repeat(2048)).unwrap();
+        let filter = MetadataFilter{ MinSize: Some(1024), ..Default::default() };
+        assert!(!filter.does_fulfill(&directory.join("small")));
+        assert!(filter.does_fulfill(&directory.join("large")));
+        let filter = MetadataFilter{ MaxSize: Some(1024), Type: Some(FileType::File), ..Default::default() };
+        assert!(filter.does_fulfill(&directory.join("small")));
+        assert!(!filter.does_fulfill(&directory.join("large")));
+        assert!(!filter.does_fulfill(&directory), "A directory is not a file.");
+        let filter = MetadataFilter{ Type: Some(FileType::Dir), ..Default::default() };
+        assert!(filter.does_fulfill(&directory));
+        assert!(MetadataFilter::default().does_fulfill(&directory.join("missing")), "Without conditions nothing is checked.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_filter_modified() {
+        let directory = test_directory("metadata-modified");
+        let old_file = directory.join("old");
+        fs::write(&old_file, "a").unwrap();
+        let old_time = SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60);
+        fs::File::options().write(true).open(&old_file).unwrap().set_modified(old_time).unwrap();
+        fs::write(directory.join("new"), "a").unwrap();
+
+        let week_ago = parse_time("7d", SystemTime::now()).unwrap();
+        let filter = MetadataFilter{ ModifiedBefore: Some(week_ago), ..Default::default() };
+        assert!(filter.does_fulfill(&old_file));
+        assert!(!filter.does_fulfill(&directory.join("new")));
+        let filter = MetadataFilter{ ModifiedAfter: Some(week_ago), ..Default::default() };
+        assert!(!filter.does_fulfill(&old_file));
+        assert!(filter.does_fulfill(&directory.join("new")));
+        let filter = MetadataFilter{ ModifiedAfter: Some(old_time), ..Default::default() };
+        assert!(!filter.does_fulfill(&old_file), "The reference time itself is not newer.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+// Transformations applied to a selected wildcard in the renamer, e.g. "(0:upper)" or "(name:lower:kebab)".
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modifier{
+    Upper,
+    Lower,
+    Title,
+    Snake,
+    Kebab,
+    Camel,
+    // numeric modifiers leave text that is not a number untouched
+    Pad(usize),
+    Hex,
+    Roman,
+}
+
+impl Modifier{
+    pub fn parse(representation: &str) -> Option<Self>{
+        match representation{
+            "upper" => Some(Modifier::Upper),
+            "lower" => Some(Modifier::Lower),
+            "title" => Some(Modifier::Title),
+            "snake" => Some(Modifier::Snake),
+            "kebab" => Some(Modifier::Kebab),
+            "camel" => Some(Modifier::Camel),
+            "hex" => Some(Modifier::Hex),
+            "roman" => Some(Modifier::Roman),
+            _ => match representation.strip_prefix("pad"){
+                Some(width) if !width.is_empty() && width.chars().all(|character| character.is_ascii_digit()) => width.parse().ok().map(Modifier::Pad),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String{
+        match self{
+            Modifier::Upper => text.to_uppercase(),
+            Modifier::Lower => text.to_lowercase(),
+            Modifier::Title => title_case(text),
+            Modifier::Snake => split_words(text).iter().map(|word| word.to_lowercase()).collect::<Vec<String>>().join("_"),
+            Modifier::Kebab => split_words(text).iter().map(|word| word.to_lowercase()).collect::<Vec<String>>().join("-"),
+            Modifier::Camel => split_words(text).iter().enumerate()
+                .map(|(index, word)| if index == 0 { word.to_lowercase() } else { capitalize(word) })
+                .collect(),
+            Modifier::Pad(width) => {
+                if !is_number(text){
+                    return text.to_string();
+                }
+                format!("{:0>width$}", text, width = width)
+            },
+            Modifier::Hex => match text.parse::<u128>(){
+                Ok(number) if is_number(text) => format!("{:x}", number),
+                _ => text.to_string(),
+            },
+            Modifier::Roman => match text.parse::<u32>(){
+                Ok(number) if is_number(text) && (1..=3999).contains(&number) => roman_numeral(number),
+                _ => text.to_string(),
+            },
+        }
+    }
+}
+
+fn is_number(text: &str) -> bool{
+    !text.is_empty() && text.chars().all(|character| character.is_ascii_digit())
+}
+
+fn roman_numeral(mut number: u32) -> String{
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"),
+        (100, "C"), (90, "XC"), (50, "L"), (40, "XL"),
+        (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut result = String::new();
+    for (value, numeral) in NUMERALS{
+        while number >= value{
+            result.push_str(numeral);
+            number -= value;
+        }
+    }
+    result
+}
+
+pub fn apply_modifiers(modifiers: &[Modifier], text: &str) -> String{
+    modifiers.iter().fold(text.to_string(), |text, modifier| modifier.apply(&text))
+}
+
+// First character upper case and the rest lower case, mapped as whole strings so
+// expansions (ß -> SS) and context dependent forms (final sigma) come out right.
+fn capitalize(word: &str) -> String{
+    let mut characters = word.chars();
+    match characters.next(){
+        Some(first) => first.to_uppercase().collect::<String>() + &characters.as_str().to_lowercase(),
+        None => String::new(),
+    }
+}
+
+// Capitalizes every word while keeping the separators in place.
+fn title_case(text: &str) -> String{
+    let mut result = String::new();
+    let mut word = String::new();
+    for character in text.chars(){
+        if character.is_alphanumeric(){
+            word.push(character);
+            continue;
+        }
+        result.push_str(&capitalize(&word));
+        word.clear();
+        result.push(character);
+    }
+    result.push_str(&capitalize(&word));
+    result
+}
+
+// Words are runs of letters and digits, a lower to upper case change also starts a new word
+// ("myFile" -> my, File) as does the last capital of an acronym ("HTTPServer" -> HTTP, Server).
+fn split_words(text: &str) -> Vec<String>{
+    let characters: Vec<char> = text.chars().collect();
+    let mut words = vec![];
+    let mut word = String::new();
+    for (index, character) in characters.iter().enumerate(){
+        if !character.is_alphanumeric(){
+            if !word.is_empty(){
+                words.push(word.clone());
+                word.clear();
+            }
+            continue;
+        }
+        if !word.is_empty() && character.is_uppercase(){
+            let previous = characters[index - 1];
+            let next_is_lower = characters.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || previous.is_numeric() || (previous.is_uppercase() && next_is_lower){
+                words.push(word.clone());
+                word.clear();
+            }
+        }
+        word.push(*character);
+    }
+    if !word.is_empty(){
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_modifiers() {
+        assert_eq!(Modifier::parse("upper"), Some(Modifier::Upper));
+        assert_eq!(Modifier::parse("camel"), Some(Modifier::Camel));
+        assert_eq!(Modifier::parse("shout"), None);
+        assert_eq!(Modifier::parse("pad4"), Some(Modifier::Pad(4)));
+        assert_eq!(Modifier::parse("pad"), None);
+        assert_eq!(Modifier::parse("padx"), None);
+    }
+
+    #[test]
+    fn test_case_modifiers() {
+        assert_eq!(Modifier::Upper.apply("straße"), "STRASSE", "Upper case follows the Unicode expansions.");
+        assert_eq!(Modifier::Lower.apply("ΟΔΟΣ"), "οδος", "Lower case uses the final sigma.");
+        assert_eq!(Modifier::Title.apply("el niño-de la casa"), "El Niño-De La Casa");
+        assert_eq!(Modifier::Title.apply("ÉCOLE"), "École");
+    }
+
+    #[test]
+    fn test_word_modifiers() {
+        assert_eq!(Modifier::Snake.apply("My Holiday-Photos"), "my_holiday_photos");
+        assert_eq!(Modifier::Kebab.apply("myHolidayPhotos"), "my-holiday-photos");
+        assert_eq!(Modifier::Camel.apply("my holiday photos"), "myHolidayPhotos");
+        assert_eq!(Modifier::Snake.apply("HTTPServer2Log"), "http_server2_log");
+        assert_eq!(Modifier::Kebab.apply("Ñandú Über"), "ñandú-über");
+    }
+
+    #[test]
+    fn test_apply_modifiers_in_order() {
+        assert_eq!(apply_modifiers(&[Modifier::Snake, Modifier::Upper], "my photo"), "MY_PHOTO");
+        assert_eq!(apply_modifiers(&[], "my photo"), "my photo");
+    }
+
+    #[test]
+    fn test_numeric_modifiers() {
+        assert_eq!(Modifier::Pad(3).apply("7"), "007");
+        assert_eq!(Modifier::Pad(2).apply("1234"), "1234", "Longer numbers are kept.");
+        assert_eq!(Modifier::Pad(3).apply("ab"), "ab", "Text that is not a number is kept.");
+        assert_eq!(Modifier::Hex.apply("255"), "ff");
+        assert_eq!(Modifier::Hex.apply("+5"), "+5");
+        assert_eq!(Modifier::Roman.apply("1994"), "MCMXCIV");
+        assert_eq!(Modifier::Roman.apply("0"), "0", "There is no roman numeral for 0.");
+        assert_eq!(apply_modifiers(&[Modifier::Hex, Modifier::Upper], "2748"), "ABC");
+    }
+}
@@ -0,0 +1,754 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameOperation{
+    pub From: PathBuf,
+    pub To: PathBuf,
+}
+
+pub struct RenamePlan{
+    pub Operations: Vec<RenameOperation>,
+    // renames dropped by the skip conflict policy
+    pub Skipped: Vec<(PathBuf, PathBuf)>,
+    overwrite_targets: HashSet<PathBuf>,
+    create_directories: bool,
+}
+
+// What to do when several files get the same new name or the new name is already taken.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictPolicy{
+    Skip,
+    Error,
+    Overwrite,
+    Suffix,
+}
+
+// Placeholder for the number in suffix patterns, e.g. " (#)" or "_#".
+pub const SUFFIX_NUMBER_PLACEHOLDER: char = '#';
+
+#[derive(Debug, PartialEq)]
+pub enum PlanError{
+    DuplicateTarget(PathBuf, Vec<PathBuf>),
+    TargetExists(PathBuf, PathBuf),
+    InvalidNewName(PathBuf, String),
+    // the new name ends with .. or . and names a directory
+    NoFileName(PathBuf, String),
+    OutsideDirectory(PathBuf, String),
+}
+
+impl fmt::Display for PlanError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            PlanError::DuplicateTarget(target, sources) => {
+                let sources: Vec<String> = sources.iter().map(|source| source.display().to_string()).collect();
+                write!(f, "{} would be the new name of several files: {}", target.display(), sources.join(", "))
+            },
+            PlanError::TargetExists(source, target) => write!(f, "Cannot rename {} because {} already exists.", source.display(), target.display()),
+            PlanError::InvalidNewName(source, new_name) => write!(f, "Cannot rename {} to '{}': the new name is empty or an absolute path.", source.display(), new_name),
+            PlanError::NoFileName(source, new_name) => write!(f, "Cannot rename {} to '{}': the new name ends in a directory instead of a file name.", source.display(), new_name),
+            PlanError::OutsideDirectory(source, new_name) => write!(f, "Cannot rename {} to '{}': the new path leaves the base directory.", source.display(), new_name),
+        }
+    }
+}
+
+pub struct ApplyFailure{
+    pub Operation: RenameOperation,
+    pub Error: io::Error,
+}
+
+// What happened when an atomic apply failed: the failing rename, the renames that were reverted
+// and the ones that could not be reverted.
+pub struct RollbackReport{
+    pub Failure: ApplyFailure,
+    pub RolledBack: Vec<RenameOperation>,
+    pub RollbackFailures: Vec<ApplyFailure>,
+}
+
+fn path_exists(path: &Path) -> bool{
+    // symlink_metadata so that dangling links also count as taken
+    fs::symlink_metadata(path).is_ok()
+}
+
+// Inserts the numbered suffix before the extension: "photo.jpg" -> "photo (1).jpg".
+fn suffixed_path(target: &Path, suffix_pattern: &str, number: usize) -> PathBuf{
+    let suffix = suffix_pattern.replace(SUFFIX_NUMBER_PLACEHOLDER, &number.to_string());
+    let stem = target.file_stem().map_or("".into(), |stem| stem.to_string_lossy());
+    let file_name = match target.extension(){
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    target.with_file_name(file_name)
+}
+
+fn rename_without_overwrite(from: &Path, to: &Path) -> io::Result<()>{
+    if path_exists(to){
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+    }
+    fs::rename(from, to)
+}
+
+// Resolves a new name, which can contain a relative path, against the directory of the file.
+// The result has to stay inside the root, .. can only go up to it.
+pub fn target_path(root: &Path, file_path: &Path, new_name: &str) -> Result<PathBuf, PlanError>{
+    let mut target = file_path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut has_name = false;
+    for component in Path::new(new_name).components(){
+        match component{
+            Component::Normal(part) => {
+                target.push(part);
+                has_name = true;
+            },
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !target.pop() || !target.starts_with(root){
+                    return Err(PlanError::OutsideDirectory(file_path.to_path_buf(), new_name.to_string()));
+                }
+                has_name = false;
+            },
+            Component::RootDir | Component::Prefix(_) => return Err(PlanError::InvalidNewName(file_path.to_path_buf(), new_name.to_string())),
+        }
+    }
+    // a name ending with .. would move the file onto a directory
+    if !has_name{
+        if new_name.is_empty(){
+            return Err(PlanError::InvalidNewName(file_path.to_path_buf(), new_name.to_string()));
+        }
+        return Err(PlanError::NoFileName(file_path.to_path_buf(), new_name.to_string()));
+    }
+    Ok(target)
+}
+
+// Operations have to run in an order where a file or directory is moved away before another one takes its path,
+// a temporary name exists before it is moved on, and the content of a directory is renamed before the directory itself.
+// Among the operations that are free to go the planned order is kept.
+fn order_operations(operations: Vec<RenameOperation>) -> Vec<RenameOperation>{
+    let index_of_source: HashMap<&PathBuf, usize> = operations.iter().enumerate().map(|(index, operation)| (&operation.From, index)).collect();
+    let index_of_target: HashMap<&PathBuf, usize> = operations.iter().enumerate().map(|(index, operation)| (&operation.To, index)).collect();
+    let mut followers: Vec<Vec<usize>> = vec![vec![]; operations.len()];
+    let mut waiting_for = vec![0; operations.len()];
+    let mut add_dependency = |first: usize, then: usize| {
+        if first != then{
+            followers[first].push(then);
+            waiting_for[then] += 1;
+        }
+    };
+    for (index, operation) in operations.iter().enumerate(){
+        if let Some(&previous) = index_of_source.get(&operation.To){
+            add_dependency(previous, index);
+        }
+        if let Some(&previous) = index_of_target.get(&operation.From){
+            add_dependency(previous, index);
+        }
+        for ancestor in operation.From.ancestors().skip(1){
+            if let Some(&directory) = index_of_source.get(&ancestor.to_path_buf()){
+                add_dependency(index, directory);
+            }
+        }
+    }
+
+    let mut ready: std::collections::BinaryHeap<Reverse<usize>> = (0..operations.len()).filter(|&index| waiting_for[index] == 0).map(Reverse).collect();
+    let mut order = vec![];
+    while let Some(Reverse(index)) = ready.pop(){
+        order.push(index);
+        for &follower in &followers[index]{
+            waiting_for[follower] -= 1;
+            if waiting_for[follower] == 0{
+                ready.push(Reverse(follower));
+            }
+        }
+    }
+    // contradicting constraints are left in the planned order, the apply refuses to overwrite anything anyway
+    let ordered: HashSet<usize> = order.iter().copied().collect();
+    order.extend((0..operations.len()).filter(|index| !ordered.contains(index)));
+
+    let mut operations: Vec<Option<RenameOperation>> = operations.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|index| operations[index].take()).collect()
+}
+
+// Creates the missing parent directories of a path and returns them, outermost first.
+fn create_parent_directories(path: &Path) -> io::Result<Vec<PathBuf>>{
+    let mut missing: Vec<PathBuf> = path.ancestors().skip(1)
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !path_exists(ancestor))
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    for directory in &missing{
+        fs::create_dir(directory)?;
+    }
+    Ok(missing)
+}
+
+// Removes directories created for a rename that did not happen or was reverted, innermost first.
+fn remove_directories(directories: &[PathBuf]){
+    for directory in directories.iter().rev(){
+        _ = fs::remove_dir(directory);
+    }
+}
+
+// Removes the directories left empty by moving the given paths away, from the old location of each path
+// up to the root, which is kept. Returns the removed directories.
+pub fn remove_emptied_directories(moved_paths: &[&Path], root: &Path) -> Vec<PathBuf>{
+    let mut candidates: Vec<&Path> = moved_paths.iter().filter_map(|path| path.parent()).collect();
+    candidates.sort_by_key(|directory| Reverse(directory.components().count()));
+    candidates.dedup();
+    let mut removed = vec![];
+    for directory in candidates{
+        for ancestor in directory.ancestors(){
+            if !ancestor.starts_with(root) || ancestor == root || removed.iter().any(|path: &PathBuf| path == ancestor){
+                break;
+            }
+            // remove_dir only removes empty directories
+            if fs::remove_dir(ancestor).is_err(){
+                break;
+            }
+            removed.push(ancestor.to_path_buf());
+        }
+    }
+    removed
+}
+
+// Hidden name next to the source that is not on disk nor used by the plan.
+fn temporary_path(source: &Path, taken: &HashSet<PathBuf>) -> PathBuf{
+    let file_name = source.file_name().map_or("".into(), |name| name.to_string_lossy());
+    let mut attempt = 0;
+    loop {
+        let candidate = source.with_file_name(format!(".{}.rename-tmp{}", file_name, attempt));
+        if !taken.contains(&candidate) && !path_exists(&candidate){
+            return candidate;
+        }
+        attempt += 1;
+    }
+}
+
+impl RenamePlan{
+    // Validates the whole old -> new mapping and orders it so no file is ever overwritten:
+    // chains are renamed from their end and cycles are broken by moving one file to a temporary name.
+    // Conflicting new names are resolved by the conflict policy, the first file keeps the name it asked for.
+    pub fn new(renames: Vec<(PathBuf, PathBuf)>, conflict_policy: &ConflictPolicy, suffix_pattern: &str) -> Result<Self, Vec<PlanError>>{
+        let mut renames: Vec<(PathBuf, PathBuf)> = renames.into_iter().filter(|(from, to)| from != to).collect();
+        let mut skipped = vec![];
+        let mut overwrite_targets = HashSet::new();
+
+        // a skipped file stays where it is, so the conflicts are checked again until nothing else is skipped
+        loop {
+            let sources: HashSet<PathBuf> = renames.iter().map(|(from, _)| from.clone()).collect();
+            let is_taken = |target: &PathBuf, claimed: &HashSet<PathBuf>| claimed.contains(target) || (!sources.contains(target) && path_exists(target));
+            let mut claimed: HashSet<PathBuf> = HashSet::new();
+            let mut resolved = vec![];
+            let mut newly_skipped = vec![];
+            let mut errors = vec![];
+            overwrite_targets.clear();
+
+            for (from, to) in &renames{
+                if !is_taken(to, &claimed){
+                    claimed.insert(to.clone());
+                    resolved.push((from.clone(), to.clone()));
+                    continue;
+                }
+                match conflict_policy{
+                    ConflictPolicy::Error => {
+                        if !claimed.contains(to){
+                            errors.push(PlanError::TargetExists(from.clone(), to.clone()));
+                        }
+                    },
+                    ConflictPolicy::Skip => newly_skipped.push((from.clone(), to.clone())),
+                    ConflictPolicy::Overwrite => {
+                        // overwriting another file of the same run would lose it, that stays an error
+                        if !claimed.contains(to){
+                            claimed.insert(to.clone());
+                            overwrite_targets.insert(to.clone());
+                            resolved.push((from.clone(), to.clone()));
+                        }
+                    },
+                    ConflictPolicy::Suffix => {
+                        let mut number = 1;
+                        let mut candidate = suffixed_path(to, suffix_pattern, number);
+                        while is_taken(&candidate, &claimed){
+                            number += 1;
+                            candidate = suffixed_path(to, suffix_pattern, number);
+                        }
+                        claimed.insert(candidate.clone());
+                        resolved.push((from.clone(), candidate));
+                    },
+                }
+            }
+
+            // duplicates are reported once per new name with every file that asked for it
+            let mut sources_by_target: BTreeMap<&PathBuf, Vec<PathBuf>> = BTreeMap::new();
+            for (from, to) in &renames{
+                sources_by_target.entry(to).or_default().push(from.clone());
+            }
+            if matches!(conflict_policy, ConflictPolicy::Error | ConflictPolicy::Overwrite){
+                let mut duplicate_errors: Vec<PlanError> = sources_by_target.iter()
+                    .filter(|(_, sources)| sources.len() > 1)
+                    .map(|(target, sources)| PlanError::DuplicateTarget((*target).clone(), sources.clone()))
+                    .collect();
+                duplicate_errors.extend(errors);
+                errors = duplicate_errors;
+            }
+            if !errors.is_empty(){
+                return Err(errors);
+            }
+
+            if newly_skipped.is_empty(){
+                // a suffixed name can turn out to be the current name of the file
+                renames = resolved.into_iter().filter(|(from, to)| from != to).collect();
+                break;
+            }
+            renames.retain(|rename| !newly_skipped.contains(rename));
+            skipped.extend(newly_skipped);
+        }
+
+        let sources: HashSet<PathBuf> = renames.iter().map(|(from, _)| from.clone()).collect();
+        let mut taken: HashSet<PathBuf> = sources.clone();
+        taken.extend(renames.iter().map(|(_, to)| to.clone()));
+        let mut pending: BTreeMap<PathBuf, PathBuf> = renames.iter().cloned().collect();
+        let mut source_of: HashMap<PathBuf, PathBuf> = renames.iter().map(|(from, to)| (to.clone(), from.clone())).collect();
+        let mut operations = vec![];
+
+        // renames whose target is not waiting to be moved away can go first
+        let mut ready: Vec<PathBuf> = pending.iter()
+            .filter(|(_, to)| !pending.contains_key(*to))
+            .map(|(from, _)| from.clone())
+            .collect();
+        ready.reverse();
+
+        while !pending.is_empty(){
+            while let Some(from) = ready.pop(){
+                let to = pending.remove(&from).unwrap();
+                operations.push(RenameOperation{ From: from.clone(), To: to });
+                // the file that wanted this name can now take it
+                if let Some(previous) = source_of.get(&from){
+                    if pending.contains_key(previous){
+                        ready.push(previous.clone());
+                    }
+                }
+            }
+            // whatever is left only forms cycles
+            let from = match pending.keys().next(){
+                Some(from) => from.clone(),
+                None => break,
+            };
+            let to = pending.remove(&from).unwrap();
+            let temporary = temporary_path(&from, &taken);
+            taken.insert(temporary.clone());
+            operations.push(RenameOperation{ From: from.clone(), To: temporary.clone() });
+            pending.insert(temporary.clone(), to.clone());
+            source_of.insert(to, temporary);
+            if let Some(previous) = source_of.get(&from){
+                ready.push(previous.clone());
+            }
+        }
+
+        Ok(RenamePlan{
+            Operations: order_operations(operations),
+            Skipped: skipped,
+            overwrite_targets,
+            create_directories: true,
+        })
+    }
+
+    // Missing directories of the new paths are created unless this is turned off, the renames into them then fail.
+    pub fn with_create_directories(mut self, create_directories: bool) -> Self{
+        self.create_directories = create_directories;
+        self
+    }
+
+    // Returns the directories that had to be created for the new path.
+    fn rename(&self, operation: &RenameOperation) -> io::Result<Vec<PathBuf>>{
+        let parent = operation.To.parent().unwrap_or(Path::new(""));
+        let created = if parent.as_os_str().is_empty() || path_exists(parent){
+            vec![]
+        }
+        else if self.create_directories{
+            create_parent_directories(&operation.To)?
+        }
+        else{
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("the directory {} does not exist", parent.display())));
+        };
+        let result = if self.overwrite_targets.contains(&operation.To){
+            fs::rename(&operation.From, &operation.To)
+        }
+        else{
+            rename_without_overwrite(&operation.From, &operation.To)
+        };
+        match result{
+            Ok(()) => Ok(created),
+            Err(error) => {
+                remove_directories(&created);
+                Err(error)
+            },
+        }
+    }
+
+    // Removes the directories that the renames left empty, the root is kept. Returns the removed directories.
+    pub fn remove_empty_directories(&self, root: &Path) -> Vec<PathBuf>{
+        let moved_paths: Vec<&Path> = self.Operations.iter().map(|operation| operation.From.as_path()).collect();
+        remove_emptied_directories(&moved_paths, root)
+    }
+
+    // Runs every operation in order, a target that appeared after planning is never overwritten
+    // unless the overwrite conflict policy chose to.
+    // on_renamed is called after each successful rename.
+    pub fn apply<F: FnMut(&RenameOperation)>(&self, mut on_renamed: F) -> Vec<ApplyFailure>{
+        let mut failures = vec![];
+        for operation in &self.Operations{
+            match self.rename(operation){
+                Ok(_) => on_renamed(operation),
+                Err(error) => failures.push(ApplyFailure{ Operation: operation.clone(), Error: error }),
+            }
+        }
+        failures
+    }
+
+    // Treats the plan as a transaction: on the first failure every rename already done is reverted,
+    // newest first, so the directory is left as it was found. The overwrite policy is refused with --atomic,
+    // a replaced file could not be brought back.
+    pub fn apply_atomic<F: FnMut(&RenameOperation)>(&self, mut on_renamed: F) -> Result<(), RollbackReport>{
+        let mut created_directories = vec![];
+        for (index, operation) in self.Operations.iter().enumerate(){
+            match self.rename(operation){
+                Ok(created) => created_directories.push(created),
+                Err(error) => {
+                    let mut rolled_back = vec![];
+                    let mut rollback_failures = vec![];
+                    for (done, created) in self.Operations[..index].iter().zip(&created_directories).rev(){
+                        match rename_without_overwrite(&done.To, &done.From){
+                            Ok(()) => {
+                                rolled_back.push(done.clone());
+                                remove_directories(created);
+                            },
+                            Err(error) => rollback_failures.push(ApplyFailure{ Operation: done.clone(), Error: error }),
+                        }
+                    }
+                    return Err(RollbackReport{
+                        Failure: ApplyFailure{ Operation: operation.clone(), Error: error },
+                        RolledBack: rolled_back,
+                        RollbackFailures: rollback_failures,
+                    });
+                },
+            }
+            on_renamed(operation);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    fn create_files(directory: &Path, names: &[&str]){
+        for name in names{
+            fs::write(directory.join(name), name).unwrap();
+        }
+    }
+
+    fn read(directory: &Path, name: &str) -> String{
+        fs::read_to_string(directory.join(name)).unwrap()
+    }
+
+    #[test]
+    fn test_plan_skips_unchanged_names() {
+        let directory = PathBuf::from("/plan");
+        let plan = RenamePlan::new(vec![(directory.join("a"), directory.join("a"))], &ConflictPolicy::Error, " (#)").unwrap();
+        assert!(plan.Operations.is_empty(), "Renaming a file to its own name is not an operation.");
+    }
+
+    #[test]
+    fn test_plan_orders_chain_from_the_end() {
+        let directory = PathBuf::from("/plan");
+        let plan = RenamePlan::new(vec![
+            (directory.join("1"), directory.join("2")),
+            (directory.join("2"), directory.join("3")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        assert_eq!(plan.Operations, vec![
+            RenameOperation{ From: directory.join("2"), To: directory.join("3") },
+            RenameOperation{ From: directory.join("1"), To: directory.join("2") },
+        ], "2 must be moved away before 1 can take its name.");
+    }
+
+    #[test]
+    fn test_apply_renames_directories_after_their_content() {
+        let directory = test_directory("planner-nested");
+        fs::create_dir_all(directory.join("2021 Trip").join("day 1")).unwrap();
+        create_files(&directory.join("2021 Trip").join("day 1"), &["a.jpg"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("2021 Trip"), directory.join("Trip 2021")),
+            (directory.join("2021 Trip").join("day 1"), directory.join("2021 Trip").join("day-1")),
+            (directory.join("2021 Trip").join("day 1").join("a.jpg"), directory.join("2021 Trip").join("day 1").join("b.jpg")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        let sources: Vec<&PathBuf> = plan.Operations.iter().map(|operation| &operation.From).collect();
+        assert_eq!(sources, vec![
+            &directory.join("2021 Trip").join("day 1").join("a.jpg"),
+            &directory.join("2021 Trip").join("day 1"),
+            &directory.join("2021 Trip"),
+        ], "The deepest entries must be renamed first.");
+        assert!(plan.apply(|_| {}).is_empty());
+        assert_eq!(read(&directory.join("Trip 2021").join("day-1"), "b.jpg"), "a.jpg");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_target_path() {
+        let root = PathBuf::from("/photos");
+        let file_path = root.join("trips").join("a.jpg");
+        assert_eq!(target_path(&root, &file_path, "b.jpg"), Ok(root.join("trips").join("b.jpg")));
+        assert_eq!(target_path(&root, &file_path, "2024/01/./b.jpg"), Ok(root.join("trips").join("2024").join("01").join("b.jpg")));
+        assert_eq!(target_path(&root, &file_path, "../sorted/b.jpg"), Ok(root.join("sorted").join("b.jpg")), ".. can go up to the root.");
+        assert_eq!(target_path(&root, &file_path, "../../b.jpg"), Err(PlanError::OutsideDirectory(file_path.clone(), "../../b.jpg".to_string())));
+        assert_eq!(target_path(&root, &file_path, "/tmp/b.jpg"), Err(PlanError::InvalidNewName(file_path.clone(), "/tmp/b.jpg".to_string())));
+        assert_eq!(target_path(&root, &file_path, "b/.."), Err(PlanError::NoFileName(file_path.clone(), "b/..".to_string())));
+        assert_eq!(target_path(&root, &file_path, ""), Err(PlanError::InvalidNewName(file_path.clone(), "".to_string())));
+        assert_eq!(
+            PlanError::NoFileName(PathBuf::from("a.jpg"), "sub/..".to_string()).to_string(),
+            "Cannot rename a.jpg to 'sub/..': the new name ends in a directory instead of a file name."
+        );
+    }
+
+    #[test]
+    fn test_plan_orders_moves_between_directories() {
+        let directory = PathBuf::from("/plan");
+        let plan = RenamePlan::new(vec![
+            (directory.join("sub").join("1"), directory.join("1")),
+            (directory.join("1"), directory.join("sub").join("2")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        assert_eq!(plan.Operations, vec![
+            RenameOperation{ From: directory.join("1"), To: directory.join("sub").join("2") },
+            RenameOperation{ From: directory.join("sub").join("1"), To: directory.join("1") },
+        ], "1 must be moved away before sub/1 can take its name, even though sub/1 is deeper.");
+    }
+
+    #[test]
+    fn test_apply_creates_and_removes_directories() {
+        let directory = test_directory("planner-directories");
+        fs::create_dir_all(directory.join("inbox").join("2024")).unwrap();
+        create_files(&directory.join("inbox").join("2024"), &["a.jpg"]);
+        let source = directory.join("inbox").join("2024").join("a.jpg");
+        let renames = vec![(source.clone(), target_path(&directory, &source, "../../sorted/2024/a.jpg").unwrap())];
+
+        let plan = RenamePlan::new(renames.clone(), &ConflictPolicy::Error, " (#)").unwrap().with_create_directories(false);
+        let failures = plan.apply(|_| {});
+        assert_eq!(failures.len(), 1, "Without creating directories the rename fails.");
+        assert!(!directory.join("sorted").exists());
+
+        let plan = RenamePlan::new(renames, &ConflictPolicy::Error, " (#)").unwrap();
+        assert!(plan.apply(|_| {}).is_empty());
+        assert_eq!(read(&directory.join("sorted").join("2024"), "a.jpg"), "a.jpg");
+        assert_eq!(plan.remove_empty_directories(&directory), vec![directory.join("inbox").join("2024"), directory.join("inbox")]);
+        assert!(directory.exists(), "The root is never removed.");
+
+        // moving the file back, like --undo, empties the directories created for it
+        let target = directory.join("sorted").join("2024").join("a.jpg");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::rename(&target, &source).unwrap();
+        assert_eq!(remove_emptied_directories(&[target.as_path()], &directory), vec![directory.join("sorted").join("2024"), directory.join("sorted")]);
+        assert!(source.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_plan_breaks_swap_with_temporary_name() {
+        let directory = PathBuf::from("/plan");
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("b")),
+            (directory.join("b"), directory.join("a")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        let temporary = directory.join(".a.rename-tmp0");
+        assert_eq!(plan.Operations, vec![
+            RenameOperation{ From: directory.join("a"), To: temporary.clone() },
+            RenameOperation{ From: directory.join("b"), To: directory.join("a") },
+            RenameOperation{ From: temporary, To: directory.join("b") },
+        ]);
+    }
+
+    #[test]
+    fn test_plan_reports_duplicate_targets() {
+        let directory = PathBuf::from("/plan");
+        let result = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("c")),
+            (directory.join("b"), directory.join("c")),
+        ], &ConflictPolicy::Error, " (#)");
+        assert_eq!(result.err(), Some(vec![
+            PlanError::DuplicateTarget(directory.join("c"), vec![directory.join("a"), directory.join("b")]),
+        ]));
+    }
+
+    #[test]
+    fn test_plan_reports_existing_targets() {
+        let directory = test_directory("planner-existing");
+        create_files(&directory, &["a", "b"]);
+        let result = RenamePlan::new(vec![(directory.join("a"), directory.join("b"))], &ConflictPolicy::Error, " (#)");
+        assert_eq!(result.err(), Some(vec![
+            PlanError::TargetExists(directory.join("a"), directory.join("b")),
+        ]), "b is not being renamed so it would be overwritten.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_swap_and_cycle() {
+        let directory = test_directory("planner-cycle");
+        create_files(&directory, &["a", "b", "1", "2", "3"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("b")),
+            (directory.join("b"), directory.join("a")),
+            (directory.join("1"), directory.join("2")),
+            (directory.join("2"), directory.join("3")),
+            (directory.join("3"), directory.join("1")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        assert!(plan.apply(|_| {}).is_empty(), "Every operation should succeed.");
+        assert_eq!(read(&directory, "a"), "b");
+        assert_eq!(read(&directory, "b"), "a");
+        assert_eq!(read(&directory, "1"), "3");
+        assert_eq!(read(&directory, "2"), "1");
+        assert_eq!(read(&directory, "3"), "2");
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 5, "No temporary file is left behind.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_does_not_overwrite_new_files() {
+        let directory = test_directory("planner-overwrite");
+        create_files(&directory, &["a"]);
+        let plan = RenamePlan::new(vec![(directory.join("a"), directory.join("b"))], &ConflictPolicy::Error, " (#)").unwrap();
+        create_files(&directory, &["b"]);
+        let failures = plan.apply(|_| {});
+        assert_eq!(failures.len(), 1, "The rename should fail instead of overwriting b.");
+        assert_eq!(failures[0].Error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(read(&directory, "b"), "b");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_atomic_rolls_back_on_failure() {
+        let directory = test_directory("planner-atomic");
+        create_files(&directory, &["a", "b", "c"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("x")),
+            (directory.join("b"), directory.join("y")),
+            (directory.join("c"), directory.join("z")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        // the last rename fails because its source disappears after planning
+        fs::remove_file(directory.join("c")).unwrap();
+        let mut renamed = vec![];
+        let report = plan.apply_atomic(|operation| renamed.push(operation.clone())).err().unwrap();
+        assert_eq!(report.Failure.Operation.From, directory.join("c"));
+        assert_eq!(report.RolledBack, vec![
+            RenameOperation{ From: directory.join("b"), To: directory.join("y") },
+            RenameOperation{ From: directory.join("a"), To: directory.join("x") },
+        ], "Renames are reverted newest first.");
+        assert!(report.RollbackFailures.is_empty());
+        assert_eq!(renamed.len(), 2);
+        assert_eq!(read(&directory, "a"), "a");
+        assert_eq!(read(&directory, "b"), "b");
+        assert!(!directory.join("x").exists() && !directory.join("y").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_atomic_removes_created_directories() {
+        let directory = test_directory("planner-atomic-directories");
+        create_files(&directory, &["a", "b"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("2024").join("01").join("a")),
+            (directory.join("b"), directory.join("b2")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        fs::remove_file(directory.join("b")).unwrap();
+        assert!(plan.apply_atomic(|_| {}).is_err());
+        assert_eq!(read(&directory, "a"), "a");
+        assert!(!directory.join("2024").exists(), "The directories created for the run are removed with the rollback.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_atomic_success() {
+        let directory = test_directory("planner-atomic-success");
+        create_files(&directory, &["a", "b"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("b")),
+            (directory.join("b"), directory.join("a")),
+        ], &ConflictPolicy::Error, " (#)").unwrap();
+        assert!(plan.apply_atomic(|_| {}).is_ok());
+        assert_eq!(read(&directory, "a"), "b");
+        assert_eq!(read(&directory, "b"), "a");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_plan_skip_policy() {
+        let directory = test_directory("planner-skip");
+        create_files(&directory, &["a", "b", "c", "taken"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("x")),
+            (directory.join("b"), directory.join("x")),
+            (directory.join("c"), directory.join("taken")),
+        ], &ConflictPolicy::Skip, " (#)").unwrap();
+        assert_eq!(plan.Operations, vec![
+            RenameOperation{ From: directory.join("a"), To: directory.join("x") },
+        ], "The first file keeps the new name.");
+        assert_eq!(plan.Skipped, vec![
+            (directory.join("b"), directory.join("x")),
+            (directory.join("c"), directory.join("taken")),
+        ]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_plan_skip_policy_keeps_skipped_files_in_place() {
+        let directory = test_directory("planner-skip-cascade");
+        create_files(&directory, &["a", "b", "taken"]);
+        // b cannot move, so a cannot take its name either
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("b")),
+            (directory.join("b"), directory.join("taken")),
+        ], &ConflictPolicy::Skip, " (#)").unwrap();
+        assert!(plan.Operations.is_empty());
+        assert_eq!(plan.Skipped.len(), 2);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_plan_suffix_policy() {
+        let directory = test_directory("planner-suffix");
+        create_files(&directory, &["a", "b", "c", "photo.jpg", "photo_1.jpg"]);
+        let plan = RenamePlan::new(vec![
+            (directory.join("a"), directory.join("photo.jpg")),
+            (directory.join("b"), directory.join("photo.jpg")),
+            (directory.join("c"), directory.join("notes")),
+        ], &ConflictPolicy::Suffix, "_#").unwrap();
+        assert_eq!(plan.Operations, vec![
+            RenameOperation{ From: directory.join("a"), To: directory.join("photo_2.jpg") },
+            RenameOperation{ From: directory.join("b"), To: directory.join("photo_3.jpg") },
+            RenameOperation{ From: directory.join("c"), To: directory.join("notes") },
+        ], "Suffixes skip names already on disk or given to another file.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_suffixed_path() {
+        assert_eq!(suffixed_path(Path::new("/d/photo.jpg"), " (#)", 1), PathBuf::from("/d/photo (1).jpg"));
+        assert_eq!(suffixed_path(Path::new("/d/README"), "_#", 2), PathBuf::from("/d/README_2"));
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let directory = test_directory("planner-overwrite-policy");
+        create_files(&directory, &["a", "b"]);
+        let plan = RenamePlan::new(vec![(directory.join("a"), directory.join("b"))], &ConflictPolicy::Overwrite, " (#)").unwrap();
+        assert!(plan.apply(|_| {}).is_empty());
+        assert_eq!(read(&directory, "b"), "a");
+
+        let result = RenamePlan::new(vec![
+            (directory.join("b"), directory.join("c")),
+            (directory.join("x"), directory.join("c")),
+        ], &ConflictPolicy::Overwrite, " (#)");
+        assert!(result.is_err(), "Files of the same run never overwrite each other.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
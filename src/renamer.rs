@@ -1,41 +1,89 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::modifiers::{apply_modifiers, Modifier};
+use crate::tokens::{FileContext, Token, TOKEN_WRAPPER};
+
 pub struct Renamer{
     pub FixedStrings: Vec<String>,
     pub PositionsOrder: Vec<usize>,
+    // modifiers applied to each selected wildcard, same order as PositionsOrder
+    pub Modifiers: Vec<Vec<Modifier>>,
+    // a token replaces the selected wildcard at the same index of PositionsOrder
+    pub Tokens: Vec<Option<Token>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RenamerError{
+    UnknownCaptureName(String),
+    UnknownModifier(String),
+    InvalidToken(String),
+    // selected position and number of wildcards of the filter
+    PositionOutOfRange(usize, usize),
+}
+
+impl fmt::Display for RenamerError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            RenamerError::UnknownCaptureName(name) => write!(f, "The new name references the capture '{}' which is not defined by the filter.", name),
+            RenamerError::UnknownModifier(modifier) => write!(f, "The new name uses the unknown modifier '{}'.", modifier),
+            RenamerError::InvalidToken(reason) => write!(f, "{}", reason),
+            RenamerError::PositionOutOfRange(position, count) => write!(f, "The new name selects the wildcard {} but the filter only has {} (positions start at 0).", position, count),
+        }
+    }
 }
 
-fn get_char(string:&String, index:&usize)->char{string.chars().nth(*index).unwrap()}
+fn get_char(string:&str, index:&usize)->char{string.chars().nth(*index).unwrap()}
+
+// Indexes count characters, not bytes, so names with accents, CJK or emoji are split on character boundaries.
+fn substring(string:&str, start:usize, end:usize)->String{string.chars().skip(start).take(end - start).collect()}
+
+enum Selector{
+    Position(usize),
+    Name(String),
+}
 
 enum AutomataResult{
-    Success(usize),
+    Success(Selector, Vec<String>),
     Failure,
 }
 
 fn selector_wrapper_automata(
     end_index: &mut usize,
-    string_representation: &String,
+    string_representation: &str,
     position_select_wrapper: &(char,char)
 ) -> AutomataResult{
-    // ( -> space -> number -> space -> )
+    // ( -> space -> (number|name) -> space -> (':' -> modifier)* -> )
     let mut state = 1;
     let mut position = 0;
+    let mut name = String::new();
+    let mut modifiers: Vec<String> = vec![];
+    let length = string_representation.chars().count();
     loop {
         *end_index += 1;
+        if *end_index >= length{
+            return AutomataResult::Failure;
+        }
+        let character = get_char(string_representation, end_index);
         match &state {
             1 =>{
-                let character = get_char(string_representation, end_index);
                 if character == ' '{
                     continue;
                 }
-                if character.is_numeric(){
+                if character.is_ascii_digit(){
                     state = 2;
                     position = character.to_digit(10).unwrap() as usize;
                     continue;
                 }
+                if character.is_alphabetic() || character == '_'{
+                    state = 4;
+                    name.push(character);
+                    continue;
+                }
                 return AutomataResult::Failure;
             },
             2 =>{
-                let character = get_char(string_representation, &end_index);
-                if character.is_numeric(){
+                if character.is_ascii_digit(){
                     position = position * 10 + character.to_digit(10).unwrap() as usize;
                     continue;
                 }
@@ -43,18 +91,75 @@ fn selector_wrapper_automata(
                     state = 3;
                     continue;
                 }
+                if character == ':'{
+                    modifiers.push(String::new());
+                    state = 6;
+                    continue;
+                }
                 if character == position_select_wrapper.1{
-                    return AutomataResult::Success(position);
+                    return AutomataResult::Success(Selector::Position(position), modifiers);
                 }
                 return AutomataResult::Failure;
             },
             3 =>{
-                let character = get_char(string_representation, &end_index);
                 if character == ' '{
                     continue;
                 }
+                if character == ':'{
+                    modifiers.push(String::new());
+                    state = 6;
+                    continue;
+                }
                 if character == position_select_wrapper.1{
-                    return AutomataResult::Success(position);
+                    return AutomataResult::Success(Selector::Position(position), modifiers);
+                }
+                return AutomataResult::Failure;
+            },
+            4 =>{
+                if character.is_alphanumeric() || character == '_'{
+                    name.push(character);
+                    continue;
+                }
+                if character == ' '{
+                    state = 5;
+                    continue;
+                }
+                if character == ':'{
+                    modifiers.push(String::new());
+                    state = 6;
+                    continue;
+                }
+                if character == position_select_wrapper.1{
+                    return AutomataResult::Success(Selector::Name(name), modifiers);
+                }
+                return AutomataResult::Failure;
+            },
+            5 =>{
+                if character == ' '{
+                    continue;
+                }
+                if character == ':'{
+                    modifiers.push(String::new());
+                    state = 6;
+                    continue;
+                }
+                if character == position_select_wrapper.1{
+                    return AutomataResult::Success(Selector::Name(name), modifiers);
+                }
+                return AutomataResult::Failure;
+            },
+            6 =>{
+                if character == ':'{
+                    modifiers.push(String::new());
+                    continue;
+                }
+                if character == position_select_wrapper.1{
+                    let selector = if name.is_empty() { Selector::Position(position) } else { Selector::Name(name) };
+                    return AutomataResult::Success(selector, modifiers.iter().map(|modifier| modifier.trim().to_string()).collect());
+                }
+                if character.is_alphanumeric() || character == ' '{
+                    modifiers.last_mut().unwrap().push(character);
+                    continue;
                 }
                 return AutomataResult::Failure;
             },
@@ -65,25 +170,91 @@ fn selector_wrapper_automata(
     }
 }
 
+fn token_automata(end_index: &mut usize, string_representation: &str) -> Option<String>{
+    // [ -> name -> ((':' | '|') -> argument and fallback)? -> ]
+    let mut state = 1;
+    let mut content = String::new();
+    let length = string_representation.chars().count();
+    loop {
+        *end_index += 1;
+        if *end_index >= length{
+            return None;
+        }
+        let character = get_char(string_representation, end_index);
+        match &state {
+            1 =>{
+                if character.is_alphanumeric() || character == '_' || character == '-'{
+                    content.push(character);
+                    continue;
+                }
+                if (character == ':' || character == '|') && !content.is_empty(){
+                    content.push(character);
+                    state = 2;
+                    continue;
+                }
+                if character == TOKEN_WRAPPER.1 && !content.is_empty(){
+                    return Some(content);
+                }
+                return None;
+            },
+            2 =>{
+                if character == TOKEN_WRAPPER.1{
+                    return Some(content);
+                }
+                content.push(character);
+            },
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
 impl Renamer {
-    pub fn new(StringRepresentation:String, WildcardChar:char, PositionSelectWrapper:(char,char)) -> Self{
+    // WildcardNames maps the capture names defined by the filter to their position, WildcardCount is the number of its wildcards.
+    pub fn new(StringRepresentation:String, WildcardChar:char, PositionSelectWrapper:(char,char), WildcardNames:&HashMap<String, usize>, WildcardCount:usize) -> Result<Self, RenamerError>{
         let mut FixedStrings: Vec<String> = Vec::new();
         let mut PositionsOrder: Vec<usize> = Vec::new();
+        let mut Modifiers: Vec<Vec<Modifier>> = Vec::new();
+        let mut Tokens: Vec<Option<Token>> = Vec::new();
 
         let mut start_index = 0;
         let mut end_index = 0;
         let mut wildcard_counter = 0;
 
-        while end_index < StringRepresentation.len() {
+        let length = StringRepresentation.chars().count();
+        while end_index < length {
             // Case where wildcard is found
             if get_char(&StringRepresentation, &end_index) == WildcardChar{
-                FixedStrings.push(StringRepresentation[start_index..end_index].to_string());
+                FixedStrings.push(substring(&StringRepresentation, start_index, end_index));
                 start_index = end_index + 1;
-                end_index = end_index + 1;
+                end_index += 1;
                 PositionsOrder.push(wildcard_counter);
+                Modifiers.push(vec![]);
+                Tokens.push(None);
                 wildcard_counter += 1;
                 continue;
             }
+            // Case where a token could be found, a capture with the same name wins when the wrapper is also [ ]
+            if get_char(&StringRepresentation, &end_index) == TOKEN_WRAPPER.0{
+                let starting_position = end_index;
+                let token = token_automata(&mut end_index, &StringRepresentation)
+                    .filter(|content| !(PositionSelectWrapper == TOKEN_WRAPPER && WildcardNames.contains_key(content)))
+                    .and_then(|content| Token::parse(&content));
+                match token{
+                    Some(Ok(token)) => {
+                        FixedStrings.push(substring(&StringRepresentation, start_index, starting_position));
+                        PositionsOrder.push(0);
+                        Modifiers.push(vec![]);
+                        Tokens.push(Some(token));
+                        start_index = end_index + 1;
+                        end_index += 1;
+                        continue;
+                    },
+                    Some(Err(reason)) => return Err(RenamerError::InvalidToken(reason)),
+                    None => end_index = starting_position,
+                }
+            }
             // Case where position select wrapper could found
             if get_char(&StringRepresentation, &end_index) == PositionSelectWrapper.0{
                 // Call automata to find the end of the position select wrapper
@@ -93,36 +264,66 @@ impl Renamer {
                     &StringRepresentation, 
                     &PositionSelectWrapper
                 ){
-                    AutomataResult::Success(position)=>{
-                        FixedStrings.push(StringRepresentation[start_index..starting_position].to_string());
+                    AutomataResult::Success(selector, modifier_names)=>{
+                        let position = match selector{
+                            Selector::Position(position) => position,
+                            Selector::Name(name) => match WildcardNames.get(&name){
+                                Some(position) => *position,
+                                None => return Err(RenamerError::UnknownCaptureName(name)),
+                            },
+                        };
+                        let mut selector_modifiers = vec![];
+                        for modifier_name in modifier_names{
+                            match Modifier::parse(&modifier_name){
+                                Some(modifier) => selector_modifiers.push(modifier),
+                                None => return Err(RenamerError::UnknownModifier(modifier_name)),
+                            }
+                        }
+                        FixedStrings.push(substring(&StringRepresentation, start_index, starting_position));
                         PositionsOrder.push(position);
+                        Modifiers.push(selector_modifiers);
+                        Tokens.push(None);
                         start_index = end_index + 1;
-                        end_index = end_index + 1;
+                        end_index += 1;
                         continue;
                     },
-                    AutomataResult::Failure =>{}
+                    AutomataResult::Failure =>{
+                        end_index = starting_position;
+                    }
                 }
             }
             end_index += 1;
         }
-        FixedStrings.push(StringRepresentation[start_index..end_index].to_string());
+        FixedStrings.push(substring(&StringRepresentation, start_index, length));
+        // every selected wildcard has to be catched by the filter, tokens do not select any
+        for (position, token) in PositionsOrder.iter().zip(&Tokens){
+            if token.is_none() && *position >= WildcardCount{
+                return Err(RenamerError::PositionOutOfRange(*position, WildcardCount));
+            }
+        }
 
-        Renamer { 
+        Ok(Renamer { 
             FixedStrings, 
-            PositionsOrder
-        }
+            PositionsOrder,
+            Modifiers,
+            Tokens,
+        })
     }
 
+    #[allow(dead_code)]
     pub fn get_fixed_strings(&self) -> Vec<String> {
         self.FixedStrings.clone()
     }
 
-    pub fn generate_rename_filename(&self, wildcard_catched: &Vec<String>) -> String{
+    pub fn generate_rename_filename(&self, wildcard_catched: &[String], context: &FileContext) -> String{
         let mut result = String::new();
 
         for i in 0..self.PositionsOrder.len(){
             result.push_str(self.FixedStrings[i].as_str());
-            result.push_str(wildcard_catched[self.PositionsOrder[i]].as_str());
+            match self.Tokens.get(i).and_then(Option::as_ref){
+                Some(token) => result.push_str(&token.value(context).unwrap_or_default()),
+                None => result.push_str(&apply_modifiers(&self.Modifiers[i], &wildcard_catched[self.PositionsOrder[i]])),
+            }
         }
         result.push_str(self.FixedStrings[self.FixedStrings.len()-1].as_str());
 
@@ -136,13 +337,13 @@ mod tests {
 
     #[test]
     fn test_renamer_new() {
-        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
         assert_eq!(renamer.FixedStrings, vec!["file_", "_name_", ""], "FixedStrings should be the strings between wildcards.");
     }
 
     #[test]
     fn test_fixed_strings_with_wildcard_char() {
-        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_", "_name_", ""],
@@ -152,7 +353,7 @@ mod tests {
 
     #[test]
     fn test_fixed_strings_with_position_select_wrapper() {
-        let renamer = Renamer::new("file_( 123 )_name_(456)".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_( 123 )_name_(456)".to_string(), '*', ('(', ')'), &HashMap::new(), 457).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_", "_name_", ""],
@@ -162,7 +363,7 @@ mod tests {
 
     #[test]
     fn test_fixed_strings_with_different_wildcard_char() {
-        let renamer = Renamer::new("file_?_name_?".to_string(), '?', ('(', ')'));
+        let renamer = Renamer::new("file_?_name_?".to_string(), '?', ('(', ')'), &HashMap::new(), 2).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_", "_name_", ""],
@@ -172,7 +373,7 @@ mod tests {
 
     #[test]
     fn test_fixed_strings_with_different_position_select_wrapper() {
-        let renamer = Renamer::new("file_[123]_name_[456]".to_string(), '*', ('[', ']'));
+        let renamer = Renamer::new("file_[123]_name_[456]".to_string(), '*', ('[', ']'), &HashMap::new(), 457).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_", "_name_", ""],
@@ -182,7 +383,7 @@ mod tests {
 
     #[test]
     fn test_fixed_strings_with_incomplete_position_select_wrapper() {
-        let renamer = Renamer::new("file_(123_name_456)".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_(123_name_456)".to_string(), '*', ('(', ')'), &HashMap::new(), 0).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_(123_name_456)"],
@@ -192,7 +393,7 @@ mod tests {
 
     #[test]
     fn test_fixed_strings_with_spaces_in_position_select_wrapper() {
-        let renamer = Renamer::new("file_(  123  )_name_(  456  )".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_(  123  )_name_(  456  )".to_string(), '*', ('(', ')'), &HashMap::new(), 457).unwrap();
         assert_eq!(
             renamer.get_fixed_strings(),
             vec!["file_", "_name_", ""],
@@ -202,9 +403,9 @@ mod tests {
 
     #[test]
     fn test_generate_rename_filename_with_wildcard_char() {
-        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_*_name_*".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
         let wildcard_catched = vec!["123".to_string(), "456".to_string()];
-        let result = renamer.generate_rename_filename(&wildcard_catched);
+        let result = renamer.generate_rename_filename(&wildcard_catched, &FileContext::default());
         assert_eq!(
             result,
             "file_123_name_456",
@@ -214,9 +415,9 @@ mod tests {
 
     #[test]
     fn test_generate_rename_filename_with_position_select_wrapper() {
-        let renamer = Renamer::new("file_(0)_name_(1)".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_(0)_name_(1)".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
         let wildcard_catched = vec!["123".to_string(), "456".to_string()];
-        let result = renamer.generate_rename_filename(&wildcard_catched);
+        let result = renamer.generate_rename_filename(&wildcard_catched, &FileContext::default());
         assert_eq!(
             result,
             "file_123_name_456",
@@ -226,9 +427,9 @@ mod tests {
 
     #[test]
     fn test_generate_rename_filename_with_both_wildcard_and_position_selectors() {
-        let renamer = Renamer::new("file_*_name_(1)_extra_(0)".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("file_*_name_(1)_extra_(0)".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
         let wildcard_catched = vec!["123".to_string(), "456".to_string()];
-        let result = renamer.generate_rename_filename(&wildcard_catched);
+        let result = renamer.generate_rename_filename(&wildcard_catched, &FileContext::default());
         assert_eq!(
             result,
             "file_123_name_456_extra_123",
@@ -237,40 +438,132 @@ mod tests {
     }
 
     #[test]
-    fn test_generate_rename_filename_with_position_out_of_bounds() {
-        let renamer = Renamer::new("file_(2)_name".to_string(), '*', ('(', ')'));
-        let wildcard_catched = vec!["123".to_string(), "456".to_string()];
-        let result = std::panic::catch_unwind(|| {
-            renamer.generate_rename_filename(&wildcard_catched)
-        });
-        assert!(
-            result.is_err(),
-            "Using a position selector out of bounds should throw an error."
-        );
+    fn test_renamer_new_with_position_out_of_bounds() {
+        let result = Renamer::new("file_(2)_name".to_string(), '*', ('(', ')'), &HashMap::new(), 2);
+        assert_eq!(result.err(), Some(RenamerError::PositionOutOfRange(2, 2)), "Selecting a position the filter does not catch should be refused.");
+        let result = Renamer::new("(3).md".to_string(), '*', ('(', ')'), &HashMap::new(), 1);
+        assert_eq!(result.err(), Some(RenamerError::PositionOutOfRange(3, 1)));
     }
 
     #[test]
-    fn test_generate_rename_filename_with_empty_captured_strings() {
-        let renamer = Renamer::new("file_(0)_name".to_string(), '*', ('(', ')'));
-        let wildcard_catched: Vec<String> = vec![];
-        let result = std::panic::catch_unwind(|| {
-            renamer.generate_rename_filename(&wildcard_catched)
-        });
-        assert!(
-            result.is_err(),
-            "Using a position selector when no strings are captured should throw an error."
-        );
+    fn test_renamer_new_without_captured_strings() {
+        let result = Renamer::new("file_(0)_name".to_string(), '*', ('(', ')'), &HashMap::new(), 0);
+        assert_eq!(result.err(), Some(RenamerError::PositionOutOfRange(0, 0)), "A filter without wildcards has nothing to select.");
+        let result = Renamer::new("*_*".to_string(), '*', ('(', ')'), &HashMap::new(), 1);
+        assert_eq!(result.err(), Some(RenamerError::PositionOutOfRange(1, 1)), "Each wildcard character takes the next wildcard.");
+        assert!(Renamer::new("[name]".to_string(), '*', ('(', ')'), &HashMap::new(), 0).is_ok(), "Tokens do not select a wildcard.");
     }
 
     #[test]
     fn test_generate_rename_filename_with_wildcard_at_beginning() {
-        let renamer = Renamer::new("*_file_name".to_string(), '*', ('(', ')'));
+        let renamer = Renamer::new("*_file_name".to_string(), '*', ('(', ')'), &HashMap::new(), 1).unwrap();
         let wildcard_catched = vec!["123".to_string()];
-        let result = renamer.generate_rename_filename(&wildcard_catched);
+        let result = renamer.generate_rename_filename(&wildcard_catched, &FileContext::default());
         assert_eq!(
             result,
             "123_file_name",
             "Filename should be generated correctly with a wildcard replacement at the beginning."
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_generate_rename_filename_with_named_selectors() {
+        let names = HashMap::from([
+            ("day".to_string(), 0),
+            ("month".to_string(), 1),
+            ("year".to_string(), 2),
+        ]);
+        let renamer = Renamer::new("(year)-( month )-(day)".to_string(), '*', ('(', ')'), &names, 3).unwrap();
+        let wildcard_catched = vec!["01".to_string(), "02".to_string(), "2021".to_string()];
+        assert_eq!(renamer.generate_rename_filename(&wildcard_catched, &FileContext::default()), "2021-02-01");
+    }
+
+    #[test]
+    fn test_renamer_new_with_unknown_name() {
+        let names = HashMap::from([("day".to_string(), 0)]);
+        let result = Renamer::new("(year)-(day)".to_string(), '*', ('(', ')'), &names, 1);
+        assert_eq!(
+            result.err(),
+            Some(RenamerError::UnknownCaptureName("year".to_string())),
+            "Referencing a name the filter does not define should be an error."
+        );
+    }
+
+    #[test]
+    fn test_fixed_strings_with_unterminated_position_select_wrapper() {
+        let renamer = Renamer::new("file_(12".to_string(), '*', ('(', ')'), &HashMap::new(), 0).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["file_(12"], "An unterminated selector is kept as text.");
+    }
+
+    #[test]
+    fn test_generate_rename_filename_with_modifiers() {
+        let names = HashMap::from([("title".to_string(), 1)]);
+        let renamer = Renamer::new("(0:upper)_(title: snake )_(1:lower:kebab)_(1:camel)_(0:title)".to_string(), '*', ('(', ')'), &names, 2).unwrap();
+        let wildcard_catched = vec!["über".to_string(), "My Holiday Photos".to_string()];
+        assert_eq!(
+            renamer.generate_rename_filename(&wildcard_catched, &FileContext::default()),
+            "ÜBER_my_holiday_photos_my-holiday-photos_myHolidayPhotos_Über",
+            "Modifiers should be applied in order to the selected wildcard."
+        );
+    }
+
+    #[test]
+    fn test_renamer_new_with_unknown_modifier() {
+        let result = Renamer::new("(0:shout)".to_string(), '*', ('(', ')'), &HashMap::new(), 1);
+        assert_eq!(result.err(), Some(RenamerError::UnknownModifier("shout".to_string())));
+    }
+
+    #[test]
+    fn test_fixed_strings_with_invalid_modifier_characters() {
+        let renamer = Renamer::new("file_(0:a.b)".to_string(), '*', ('(', ')'), &HashMap::new(), 1).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["file_(0:a.b)"], "A selector with unexpected characters is kept as text.");
+    }
+
+    #[test]
+    fn test_generate_rename_filename_with_numeric_modifiers() {
+        let renamer = Renamer::new("(0:pad3)_(0:roman)_(1:pad3)".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
+        let wildcard_catched = vec!["7".to_string(), "seven".to_string()];
+        assert_eq!(renamer.generate_rename_filename(&wildcard_catched, &FileContext::default()), "007_VII_seven");
+    }
+
+    #[test]
+    fn test_renamer_new_unicode() {
+        let names = HashMap::from([("título".to_string(), 0)]);
+        let renamer = Renamer::new("🎵 (título:upper) – *_「(1)」.mp3".to_string(), '*', ('(', ')'), &names, 2).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["🎵 ", " – ", "_「", "」.mp3"]);
+        let wildcard_catched = vec!["niño".to_string(), "さくら".to_string()];
+        assert_eq!(renamer.generate_rename_filename(&wildcard_catched, &FileContext::default()), "🎵 NIÑO – niño_「さくら」.mp3");
+    }
+
+    #[test]
+    fn test_renamer_new_unicode_wrapper() {
+        let renamer = Renamer::new("«0»_é".to_string(), '*', ('«', '»'), &HashMap::new(), 1).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["", "_é"]);
+        assert_eq!(renamer.PositionsOrder, vec![0]);
+    }
+
+    #[test]
+    fn test_generate_rename_filename_with_path_tokens() {
+        let renamer = Renamer::new("[parent:2] S(0)E(1) - [stem].[ext]".to_string(), '*', ('(', ')'), &HashMap::new(), 2).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["", " S", "E", " - ", ".", ""]);
+        let context = FileContext::new(std::path::Path::new("/shows/Lost/Season 02/Episode 5.mkv"), std::path::Path::new("/shows"));
+        let wildcard_catched = vec!["02".to_string(), "05".to_string()];
+        assert_eq!(renamer.generate_rename_filename(&wildcard_catched, &context), "Lost S02E05 - Episode 5.mkv");
+    }
+
+    #[test]
+    fn test_renamer_tokens_and_brackets() {
+        let renamer = Renamer::new("[1080p] [name".to_string(), '*', ('(', ')'), &HashMap::new(), 0).unwrap();
+        assert_eq!(renamer.get_fixed_strings(), vec!["[1080p] [name"], "Unknown or unclosed brackets are kept as text.");
+        assert!(matches!(Renamer::new("[parent:x]".to_string(), '*', ('(', ')'), &HashMap::new(), 0), Err(RenamerError::InvalidToken(_))));
+
+        let names = HashMap::from([("name".to_string(), 1)]);
+        let renamer = Renamer::new("[name]_[stem]".to_string(), '*', ('[', ']'), &names, 2).unwrap();
+        assert_eq!(renamer.PositionsOrder, vec![1, 0]);
+        assert_eq!(renamer.Tokens, vec![None, Some(Token::Stem)], "A capture named like a token wins when the wrapper is [ ].");
+
+        let renamer = Renamer::new("[exif:DateTimeOriginal:%H:%M|no date]".to_string(), '*', ('(', ')'), &HashMap::new(), 0).unwrap();
+        let expected = Token::Fallback(Box::new(Token::Exif("DateTimeOriginal".to_string(), Some("%H:%M".to_string()))), "no date".to_string());
+        assert_eq!(renamer.Tokens, vec![Some(expected)]);
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::filters::{Normalization, RenameFilter};
+use crate::renamer::Renamer;
+use crate::tokens::FileContext;
+
+// A filter and the new name given to the files it matches, with the options used to read both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule{
+    pub Name: String,
+    pub Filter: String,
+    pub NewName: String,
+    pub WildcardChar: char,
+    pub PositionSelectWrapper: (char, char),
+    pub Regex: bool,
+    pub IgnoreCase: bool,
+    pub Normalize: Option<Normalization>,
+    // files matching one of these are left to the next rules
+    pub Excludes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RulesError{
+    Io(String),
+    Syntax(usize, String),
+    Invalid(String, String),
+}
+
+impl fmt::Display for RulesError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            RulesError::Io(error) => write!(f, "Could not read the rules file: {}", error),
+            RulesError::Syntax(line, message) => write!(f, "Rules file, line {}: {}", line, message),
+            RulesError::Invalid(rule, message) => write!(f, "Rule '{}': {}", rule, message),
+        }
+    }
+}
+
+enum Value{
+    Text(String),
+    List(Vec<String>),
+}
+
+// Reads a quoted string starting at the opening quote, returns it with the rest of the line.
+// Double quotes support the \" \\ \n \t escapes, single quotes take the text as it is.
+fn parse_quoted(text: &str) -> Result<(String, &str), String>{
+    let mut characters = text.char_indices();
+    let quote = match characters.next(){
+        Some((_, quote)) => quote,
+        None => return Err("Expected a quoted string.".to_string()),
+    };
+    let mut result = String::new();
+    while let Some((index, character)) = characters.next(){
+        if character == quote{
+            return Ok((result, &text[index + 1..]));
+        }
+        if character == '\\' && quote == '"'{
+            match characters.next(){
+                Some((_, 'n')) => result.push('\n'),
+                Some((_, 't')) => result.push('\t'),
+                Some((_, escaped)) => result.push(escaped),
+                None => break,
+            }
+            continue;
+        }
+        result.push(character);
+    }
+    Err("The string is not closed.".to_string())
+}
+
+fn expect_end_of_line(rest: &str) -> Result<(), String>{
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#'){
+        return Ok(());
+    }
+    Err(format!("Unexpected '{}' after the value.", rest))
+}
+
+// Values are quoted strings, lists of quoted strings or bare text up to the end of the line.
+fn parse_value(text: &str) -> Result<Value, String>{
+    let text = text.trim();
+    if text.starts_with('"') || text.starts_with('\''){
+        let (value, rest) = parse_quoted(text)?;
+        expect_end_of_line(rest)?;
+        return Ok(Value::Text(value));
+    }
+    if let Some(mut rest) = text.strip_prefix('['){
+        let mut values = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']'){
+                expect_end_of_line(after)?;
+                return Ok(Value::List(values));
+            }
+            let (value, after) = parse_quoted(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+    Ok(Value::Text(text.to_string()))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String>{
+    match value{
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Expected true or false, found '{}'.", value)),
+    }
+}
+
+fn set_option(rule: &mut Rule, key: &str, value: Value) -> Result<(), String>{
+    let text = match value{
+        Value::List(values) if key == "exclude" => {
+            rule.Excludes.extend(values);
+            return Ok(());
+        },
+        Value::List(_) => return Err(format!("The option '{}' does not take a list.", key)),
+        Value::Text(text) => text,
+    };
+    match key{
+        "filter" => rule.Filter = text,
+        "new-name" | "new_name" | "template" => rule.NewName = text,
+        "exclude" => rule.Excludes.push(text),
+        "wildcard-char" | "wildcard_char" => {
+            let mut characters = text.chars();
+            match (characters.next(), characters.next()){
+                (Some(character), None) => rule.WildcardChar = character,
+                _ => return Err("The wildcard char must be a single character.".to_string()),
+            }
+        },
+        "position-select-wrapper" | "position_select_wrapper" => {
+            let characters: Vec<char> = text.chars().collect();
+            if characters.len() != 2{
+                return Err("The position select wrapper must be two characters.".to_string());
+            }
+            rule.PositionSelectWrapper = (characters[0], characters[1]);
+        },
+        "regex" => rule.Regex = parse_bool(&text)?,
+        "ignore-case" | "ignore_case" => rule.IgnoreCase = parse_bool(&text)?,
+        "normalize" => rule.Normalize = match text.to_lowercase().as_str(){
+            "nfc" => Some(Normalization::Nfc),
+            "nfd" => Some(Normalization::Nfd),
+            "none" => None,
+            _ => return Err("The normalization must be nfc, nfd or none.".to_string()),
+        },
+        _ => return Err(format!("Unknown option '{}'.", key)),
+    }
+    Ok(())
+}
+
+// Rules start with a `[name]` (or TOML `[[rule]]`) header followed by `key = value` lines, '#' starts a comment.
+// Options that a rule does not set are taken from the defaults, the order of the rules is kept.
+pub fn parse_rules(content: &str, defaults: &Rule) -> Result<Vec<Rule>, RulesError>{
+    let mut rules: Vec<Rule> = vec![];
+    for (line_index, line) in content.lines().enumerate(){
+        let line_number = line_index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#'){
+            continue;
+        }
+        if line.starts_with('['){
+            let name = line.trim_start_matches('[').trim_end_matches(']').trim();
+            if !line.ends_with(']') || name.is_empty(){
+                return Err(RulesError::Syntax(line_number, "Expected a rule header like [photos].".to_string()));
+            }
+            let mut rule = defaults.clone();
+            // every TOML array entry has the same header, number them to tell them apart
+            rule.Name = if line.starts_with("[[") { format!("{} {}", name, rules.len() + 1) } else { name.to_string() };
+            rules.push(rule);
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(RulesError::Syntax(line_number, "Expected an option like filter = \"*.jpg\".".to_string()));
+        };
+        let Some(rule) = rules.last_mut() else {
+            return Err(RulesError::Syntax(line_number, "Options must follow a rule header.".to_string()));
+        };
+        let value = parse_value(value).map_err(|message| RulesError::Syntax(line_number, message))?;
+        set_option(rule, key.trim(), value).map_err(|message| RulesError::Syntax(line_number, message))?;
+    }
+    for rule in &rules{
+        if rule.Filter.is_empty(){
+            return Err(RulesError::Invalid(rule.Name.clone(), "The filter is missing.".to_string()));
+        }
+        if rule.NewName.is_empty(){
+            return Err(RulesError::Invalid(rule.Name.clone(), "The new name is missing.".to_string()));
+        }
+    }
+    Ok(rules)
+}
+
+pub fn read_rules_file(file_path: &Path, defaults: &Rule) -> Result<Vec<Rule>, RulesError>{
+    let content = fs::read_to_string(file_path).map_err(|error| RulesError::Io(error.to_string()))?;
+    parse_rules(&content, defaults)
+}
+
+fn build_filter(rule: &Rule, representation: &str) -> Result<RenameFilter, RulesError>{
+    let mut filter = if rule.Regex{
+        RenameFilter::new_regex(representation.to_string())
+            .map_err(|error| RulesError::Invalid(rule.Name.clone(), format!("Invalid regex filter: {}", error)))?
+    }
+    else{
+        RenameFilter::new(representation.to_string(), rule.WildcardChar)
+    };
+    if rule.IgnoreCase{
+        filter = filter.with_ignore_case();
+    }
+    if let Some(normalization) = rule.Normalize{
+        filter = filter.with_normalization(normalization);
+    }
+    Ok(filter)
+}
+
+// The compiled rules, a file is renamed by the first rule whose filter matches and whose excludes do not.
+pub struct RuleSet{
+    filters: Vec<RenameFilter>,
+    renamers: Vec<Renamer>,
+    excludes: Vec<Vec<RenameFilter>>,
+    // excludes of the command line, they veto every rule
+    global_excludes: Vec<RenameFilter>,
+}
+
+impl RuleSet{
+    pub fn new(rules: &[Rule]) -> Result<Self, RulesError>{
+        let mut filters = vec![];
+        let mut renamers = vec![];
+        let mut excludes = vec![];
+        for rule in rules{
+            let filter = build_filter(rule, &rule.Filter)?;
+            let renamer = Renamer::new(rule.NewName.clone(), rule.WildcardChar, rule.PositionSelectWrapper, &filter.get_wildcard_names(), filter.get_wildcard_count())
+                .map_err(|error| RulesError::Invalid(rule.Name.clone(), error.to_string()))?;
+            let mut rule_excludes = vec![];
+            for exclude in &rule.Excludes{
+                rule_excludes.push(build_filter(rule, exclude)?);
+            }
+            filters.push(filter);
+            renamers.push(renamer);
+            excludes.push(rule_excludes);
+        }
+        Ok(RuleSet{ filters, renamers, excludes, global_excludes: vec![] })
+    }
+
+    // The excludes are read with the options of the given rule, not with the options of each rule.
+    pub fn with_excludes(mut self, excludes: &[String], options: &Rule) -> Result<Self, RulesError>{
+        for exclude in excludes{
+            self.global_excludes.push(build_filter(options, exclude)?);
+        }
+        Ok(self)
+    }
+
+    // Index of the rule that renames the file, if any.
+    pub fn find(&self, file_name: &str) -> Option<usize>{
+        if self.global_excludes.iter().any(|exclude| exclude.does_fulfill(file_name)){
+            return None;
+        }
+        (0..self.filters.len()).find(|index| {
+            self.filters[*index].does_fulfill(file_name)
+                && !self.excludes[*index].iter().any(|exclude| exclude.does_fulfill(file_name))
+        })
+    }
+
+    pub fn get_filter(&self, index: usize) -> &RenameFilter{
+        &self.filters[index]
+    }
+
+    // Capture names defined by any of the filters.
+    pub fn get_wildcard_names(&self) -> HashMap<String, usize>{
+        let mut wildcard_names = HashMap::new();
+        for filter in &self.filters{
+            for (name, position) in filter.get_wildcard_names(){
+                wildcard_names.entry(name).or_insert(position);
+            }
+        }
+        wildcard_names
+    }
+
+    // New name of a file matched by the rule, counters of that rule advance.
+    pub fn rename(&mut self, index: usize, file_name: &str, context: &FileContext) -> String{
+        let wildcard_catched = self.filters[index].collect_wildcards(file_name);
+        self.renamers[index].generate_rename_filename(&wildcard_catched, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Rule{
+        Rule{
+            Name: String::new(),
+            Filter: String::new(),
+            NewName: String::new(),
+            WildcardChar: '*',
+            PositionSelectWrapper: ('(', ')'),
+            Regex: false,
+            IgnoreCase: false,
+            Normalize: None,
+            Excludes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let content = r#"
+# ingest rules
+[photos]
+filter = IMG_*.jpg
+new-name = photo_*.jpg
+exclude = *_thumb.*
+
+[scans]
+filter = "scan-$.tif"   # comment after a quoted value
+new_name = 'page \$.tif'
+wildcard_char = "$"
+position_select_wrapper = "[]"
+ignore_case = true
+"#;
+        let rules = parse_rules(content, &defaults()).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].Name, "photos");
+        assert_eq!(rules[0].Filter, "IMG_*.jpg", "Bare values run to the end of the line.");
+        assert_eq!(rules[0].NewName, "photo_*.jpg");
+        assert_eq!(rules[0].Excludes, vec!["*_thumb.*"]);
+        assert_eq!(rules[1].Filter, "scan-$.tif");
+        assert_eq!(rules[1].NewName, "page \\$.tif", "Single quoted strings are taken as they are.");
+        assert_eq!(rules[1].WildcardChar, '$');
+        assert_eq!(rules[1].PositionSelectWrapper, ('[', ']'));
+        assert!(rules[1].IgnoreCase);
+        assert!(!rules[0].IgnoreCase, "Options are not shared between rules.");
+    }
+
+    #[test]
+    fn test_parse_toml_rules() {
+        let content = r#"
+[[rule]]
+filter = "(\\d+)\\.jpg"
+template = "photo_(0).jpg"
+regex = true
+exclude = ["0*.jpg", "9*.jpg"]
+normalize = "NFC"
+
+[[rule]]
+filter = "*.png"
+template = "image_*.png"
+"#;
+        let rules = parse_rules(content, &defaults()).unwrap();
+        assert_eq!(rules[0].Name, "rule 1");
+        assert_eq!(rules[1].Name, "rule 2");
+        assert_eq!(rules[0].Filter, "(\\d+)\\.jpg", "Escapes in double quoted strings are resolved.");
+        assert!(rules[0].Regex);
+        assert_eq!(rules[0].Excludes, vec!["0*.jpg", "9*.jpg"]);
+        assert_eq!(rules[0].Normalize, Some(Normalization::Nfc));
+    }
+
+    #[test]
+    fn test_parse_rules_errors() {
+        assert_eq!(
+            parse_rules("filter = *.jpg", &defaults()),
+            Err(RulesError::Syntax(1, "Options must follow a rule header.".to_string()))
+        );
+        assert_eq!(
+            parse_rules("[a]\nfilter = \"*.jpg", &defaults()),
+            Err(RulesError::Syntax(2, "The string is not closed.".to_string()))
+        );
+        assert!(matches!(parse_rules("[a]\ncolour = red", &defaults()), Err(RulesError::Syntax(2, _))));
+        assert!(matches!(parse_rules("[a]\nregex = yes", &defaults()), Err(RulesError::Syntax(2, _))));
+        assert!(matches!(parse_rules("[a]\nfilter", &defaults()), Err(RulesError::Syntax(2, _))));
+        assert_eq!(
+            parse_rules("[a]\nfilter = *.jpg", &defaults()),
+            Err(RulesError::Invalid("a".to_string(), "The new name is missing.".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rule_set_first_match_wins() {
+        let content = r#"
+[thumbs]
+filter = *_thumb.jpg
+new-name = thumbs_*.jpg
+exclude = keep_*
+
+[photos]
+filter = *.jpg
+new-name = photo_*.jpg
+
+[all]
+filter = *.*
+new-name = other_*.*
+"#;
+        let rules = parse_rules(content, &defaults()).unwrap();
+        let mut rule_set = RuleSet::new(&rules).unwrap();
+        assert_eq!(rule_set.find("beach_thumb.jpg"), Some(0));
+        assert_eq!(rule_set.find("keep_thumb.jpg"), Some(1), "An excluded file falls through to the next rule.");
+        assert_eq!(rule_set.find("notes.txt"), Some(2));
+        assert_eq!(rule_set.find("README"), None);
+        assert_eq!(rule_set.rename(0, "beach_thumb.jpg", &FileContext::default()), "thumbs_beach.jpg");
+        assert_eq!(rule_set.rename(2, "notes.txt", &FileContext::default()), "other_notes.txt");
+
+        let mut options = defaults();
+        options.WildcardChar = '$';
+        let rule_set = rule_set.with_excludes(&["$.txt".to_string()], &options).unwrap();
+        assert_eq!(rule_set.find("notes.txt"), None, "Global excludes veto every rule.");
+        assert_eq!(rule_set.find("beach_thumb.jpg"), Some(0));
+    }
+
+    #[test]
+    fn test_rule_set_invalid_rule() {
+        let mut rule = defaults();
+        rule.Name = "broken".to_string();
+        rule.Filter = "*.jpg".to_string();
+        rule.NewName = "(missing).jpg".to_string();
+        assert!(matches!(RuleSet::new(&[rule]), Err(RulesError::Invalid(name, _)) if name == "broken"));
+    }
+
+    #[test]
+    fn test_rule_set_position_out_of_range() {
+        let mut rule = defaults();
+        rule.Name = "markdown".to_string();
+        rule.Filter = "*.txt".to_string();
+        rule.NewName = "(3).md".to_string();
+        let mut other = defaults();
+        other.Name = "fine".to_string();
+        other.Filter = "*_*.jpg".to_string();
+        other.NewName = "(1)_(0).jpg".to_string();
+        assert!(matches!(RuleSet::new(&[other, rule]), Err(RulesError::Invalid(name, _)) if name == "markdown"), "Each rule is checked against its own filter.");
+    }
+}
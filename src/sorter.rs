@@ -0,0 +1,245 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::filters::RenameFilter;
+
+enum SortField{
+    Name,
+    Natural,
+    Modified,
+    Created,
+    Size,
+    Extension,
+    Capture(usize),
+    // resolved with the names of the filter that matched the file
+    CaptureName(String),
+}
+
+struct SortKey{
+    field: SortField,
+    descending: bool,
+}
+
+enum SortValue{
+    Text(String),
+    Number(u128),
+}
+
+// Orders the matched files before the wildcards are collected, so counters follow a predictable order.
+pub struct FileSorter{
+    keys: Vec<SortKey>,
+    reverse: bool,
+}
+
+fn timestamp(time: std::io::Result<SystemTime>) -> u128{
+    time.ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos())
+}
+
+// Compares digit runs by their numeric value so "img2" comes before "img10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering{
+    let mut a_characters = a.chars().peekable();
+    let mut b_characters = b.chars().peekable();
+    loop {
+        match (a_characters.peek(), b_characters.peek()){
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_character), Some(b_character)) if a_character.is_ascii_digit() && b_character.is_ascii_digit() => {
+                let mut a_number = String::new();
+                while let Some(character) = a_characters.next_if(|character| character.is_ascii_digit()){
+                    a_number.push(character);
+                }
+                let mut b_number = String::new();
+                while let Some(character) = b_characters.next_if(|character| character.is_ascii_digit()){
+                    b_number.push(character);
+                }
+                let a_trimmed = a_number.trim_start_matches('0');
+                let b_trimmed = b_number.trim_start_matches('0');
+                let ordering = a_trimmed.len().cmp(&b_trimmed.len()).then_with(|| a_trimmed.cmp(b_trimmed));
+                if ordering != Ordering::Equal{
+                    return ordering;
+                }
+            },
+            (Some(a_character), Some(b_character)) => {
+                let ordering = a_character.cmp(b_character);
+                if ordering != Ordering::Equal{
+                    return ordering;
+                }
+                a_characters.next();
+                b_characters.next();
+            },
+        }
+    }
+}
+
+impl FileSorter{
+    // Keys are separated by commas and compared in order: name, natural, mtime, ctime, size, ext
+    // or capture:N / capture:name. A leading '-' sorts that key descending and `reverse` flips the whole order.
+    pub fn new(sort_representation: &str, wildcard_names: &HashMap<String, usize>) -> Result<Self, String>{
+        let mut keys = vec![];
+        let mut reverse = false;
+        for key in sort_representation.split(',').map(str::trim){
+            if key == "reverse"{
+                reverse = !reverse;
+                continue;
+            }
+            let (descending, field) = match key.strip_prefix('-'){
+                Some(field) => (true, field),
+                None => (false, key),
+            };
+            let field = match field{
+                "name" => SortField::Name,
+                "natural" => SortField::Natural,
+                "mtime" => SortField::Modified,
+                "ctime" => SortField::Created,
+                "size" => SortField::Size,
+                "ext" | "extension" => SortField::Extension,
+                _ => match field.strip_prefix("capture:"){
+                    Some(capture) => match capture.parse::<usize>(){
+                        Ok(position) => SortField::Capture(position),
+                        Err(_) => match wildcard_names.contains_key(capture){
+                            true => SortField::CaptureName(capture.to_string()),
+                            false => return Err(format!("The sort key '{}' references a capture the filter does not define.", key)),
+                        },
+                    },
+                    None => return Err(format!("Unknown sort key '{}'.", key)),
+                },
+            };
+            keys.push(SortKey{ field, descending });
+        }
+        Ok(FileSorter{ keys, reverse })
+    }
+
+    fn sort_value(&self, field: &SortField, file_path: &Path, filter: Option<&RenameFilter>) -> SortValue{
+        let file_name = file_path.file_name().map_or("".into(), |name| name.to_string_lossy());
+        let metadata = fs::metadata(file_path).ok();
+        match field{
+            SortField::Name | SortField::Natural => SortValue::Text(file_name.to_string()),
+            SortField::Extension => SortValue::Text(file_path.extension().map_or("".into(), |extension| extension.to_string_lossy().to_string())),
+            SortField::Size => SortValue::Number(metadata.map_or(0, |metadata| metadata.len() as u128)),
+            SortField::Modified => SortValue::Number(metadata.map_or(0, |metadata| timestamp(metadata.modified()))),
+            // creation time is not available everywhere, the modification time stands in for it
+            SortField::Created => SortValue::Number(metadata.map_or(0, |metadata| timestamp(metadata.created().or(metadata.modified())))),
+            SortField::Capture(position) => {
+                let catched = filter.and_then(|filter| filter.peek_wildcards(&file_name).into_iter().nth(*position).flatten());
+                SortValue::Text(catched.unwrap_or_default())
+            },
+            SortField::CaptureName(name) => {
+                let catched = filter.and_then(|filter| {
+                    let position = *filter.get_wildcard_names().get(name)?;
+                    filter.peek_wildcards(&file_name).into_iter().nth(position).flatten()
+                });
+                SortValue::Text(catched.unwrap_or_default())
+            },
+        }
+    }
+
+    // Captures are taken from the filter that filter_of gives for the file name.
+    pub fn sort<'a, F: Fn(&str) -> Option<&'a RenameFilter>>(&self, files: &mut Vec<PathBuf>, filter_of: F){
+        let mut decorated: Vec<(Vec<SortValue>, PathBuf)> = files.drain(..)
+            .map(|file_path| {
+                let file_name = file_path.file_name().map_or("".into(), |name| name.to_string_lossy());
+                let filter = filter_of(&file_name);
+                let values = self.keys.iter().map(|key| self.sort_value(&key.field, &file_path, filter)).collect();
+                (values, file_path)
+            })
+            .collect();
+        decorated.sort_by(|(a_values, a_path), (b_values, b_path)| {
+            let mut ordering = Ordering::Equal;
+            for (key, (a_value, b_value)) in self.keys.iter().zip(a_values.iter().zip(b_values)){
+                ordering = match (a_value, b_value){
+                    (SortValue::Number(a), SortValue::Number(b)) => a.cmp(b),
+                    (SortValue::Text(a), SortValue::Text(b)) => match key.field{
+                        SortField::Natural | SortField::Capture(_) | SortField::CaptureName(_) => natural_cmp(a, b),
+                        _ => a.cmp(b),
+                    },
+                    _ => Ordering::Equal,
+                };
+                if key.descending{
+                    ordering = ordering.reverse();
+                }
+                if ordering != Ordering::Equal{
+                    break;
+                }
+            }
+            // the full path keeps the order stable between runs
+            ordering = ordering.then_with(|| a_path.cmp(b_path));
+            if self.reverse{
+                ordering = ordering.reverse();
+            }
+            ordering
+        });
+        files.extend(decorated.into_iter().map(|(_, file_path)| file_path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf>{
+        names.iter().map(|name| PathBuf::from("/sort").join(name)).collect()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("img2", "img10"), Ordering::Less);
+        assert_eq!(natural_cmp("img10", "img9"), Ordering::Greater);
+        assert_eq!(natural_cmp("img007", "img7"), Ordering::Less, "Equal numbers fall back to the text.");
+        assert_eq!(natural_cmp("a", "b"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_sort_by_name_and_natural() {
+        let filter = RenameFilter::new("*".to_string(), '*');
+        let mut files = paths(&["img10", "img2", "img1"]);
+        FileSorter::new("name", &HashMap::new()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["img1", "img10", "img2"]));
+        FileSorter::new("natural", &HashMap::new()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["img1", "img2", "img10"]));
+        FileSorter::new("natural,reverse", &HashMap::new()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["img10", "img2", "img1"]));
+    }
+
+    #[test]
+    fn test_sort_by_multiple_keys() {
+        let filter = RenameFilter::new("*".to_string(), '*');
+        let mut files = paths(&["b.png", "a.jpg", "c.jpg", "a.png"]);
+        FileSorter::new("-ext, name", &HashMap::new()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["a.png", "b.png", "a.jpg", "c.jpg"]));
+    }
+
+    #[test]
+    fn test_sort_by_capture() {
+        let filter = RenameFilter::new("*{day}-*{month}.jpg".to_string(), '*');
+        let mut files = paths(&["01-12.jpg", "02-3.jpg", "03-11.jpg"]);
+        FileSorter::new("capture:1", &HashMap::new()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["02-3.jpg", "03-11.jpg", "01-12.jpg"]));
+        FileSorter::new("-capture:day", &filter.get_wildcard_names()).unwrap().sort(&mut files, |_| Some(&filter));
+        assert_eq!(files, paths(&["03-11.jpg", "02-3.jpg", "01-12.jpg"]));
+    }
+
+    #[test]
+    fn test_invalid_sort_keys() {
+        assert!(FileSorter::new("date", &HashMap::new()).is_err(), "Unknown keys are rejected.");
+        assert!(FileSorter::new("capture:year", &HashMap::new()).is_err(), "Unknown capture names are rejected.");
+    }
+
+    #[test]
+    fn test_sort_by_capture_name_of_matching_filter() {
+        let filters = [
+            RenameFilter::new("*{day}-*{month}.jpg".to_string(), '*'),
+            RenameFilter::new("*{month}_*{day}.png".to_string(), '*'),
+        ];
+        let mut names = filters[0].get_wildcard_names();
+        names.extend(filters[1].get_wildcard_names());
+        let mut files = paths(&["01-12.jpg", "3_02.png", "03-11.jpg"]);
+        FileSorter::new("capture:month", &names).unwrap().sort(&mut files, |file_name| filters.iter().find(|filter| filter.does_fulfill(file_name)));
+        assert_eq!(files, paths(&["3_02.png", "03-11.jpg", "01-12.jpg"]), "Each file is sorted by the capture of the filter it matches.");
+    }
+}
@@ -0,0 +1,11 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Empty directory for a test, unique to the name and the process. The test removes it when it is done.
+pub fn test_directory(name: &str) -> PathBuf{
+    let directory = env::temp_dir().join(format!("file-renamer-{}-{}", name, std::process::id()));
+    _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
@@ -0,0 +1,493 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{self, Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use exif::{Exif, In, Value};
+
+use crate::audio::{self, AudioTags};
+use crate::hashing::{self, HashAlgorithm};
+use crate::modifiers::{apply_modifiers, Modifier};
+
+// Tokens are written [name] or [name:argument] in the new name and are replaced by information about the file
+// instead of text catched by the filter. [token|text] writes text when the file has no value for the token.
+pub const TOKEN_WRAPPER: (char, char) = ('[', ']');
+
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeKind{
+    Modified,
+    Created,
+    Accessed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token{
+    // name of the Nth directory above the file, 1 is the directory of the file
+    Ancestor(usize),
+    // directory of the file relative to the base directory, its directories joined by the separator
+    RelativePath(String),
+    Name,
+    Stem,
+    Extension,
+    // timestamp of the file with a strftime format
+    Time(TimeKind, String),
+    // EXIF tag of an image by name, dates can be given a strftime format
+    Exif(String, Option<String>),
+    // tag of an audio file by lowercase name and the modifiers applied to its value
+    AudioTag(String, Vec<Modifier>),
+    // first characters of the digest of the content, all of it without a length
+    Hash(HashAlgorithm, Option<usize>),
+    // number of the file among the duplicates of the same original, 1 for the first
+    DuplicateNumber,
+    // name without extension of the file a duplicate has the content of
+    Original,
+    Fallback(Box<Token>, String),
+}
+
+// The file a new name is generated for and the base directory it was found in.
+#[derive(Default)]
+pub struct FileContext{
+    pub Path: PathBuf,
+    pub Root: PathBuf,
+    // timestamps are formatted in UTC instead of the local time zone
+    pub Utc: bool,
+    // set with --find-duplicates: the number of the duplicate and the original of its group
+    pub Duplicate: Option<(usize, PathBuf)>,
+    // read on the first EXIF token, None when the file has no EXIF block
+    exif: OnceCell<Option<Exif>>,
+    audio_tags: OnceCell<Option<AudioTags>>,
+    hashes: RefCell<HashMap<HashAlgorithm, Option<String>>>,
+}
+
+impl FileContext{
+    pub fn new(file_path: &Path, root: &Path) -> Self{
+        FileContext{
+            Path: file_path.to_path_buf(),
+            Root: root.to_path_buf(),
+            Utc: false,
+            Duplicate: None,
+            exif: OnceCell::new(),
+            audio_tags: OnceCell::new(),
+            hashes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_utc(mut self, utc: bool) -> Self{
+        self.Utc = utc;
+        self
+    }
+
+    pub fn with_duplicate(mut self, number: usize, original: &Path) -> Self{
+        self.Duplicate = Some((number, original.to_path_buf()));
+        self
+    }
+
+    // JPEG, TIFF and the RAW formats based on TIFF (CR2, NEF, ARW, DNG, ...), HEIF, PNG and WebP are read.
+    fn exif(&self) -> Option<&Exif>{
+        self.exif.get_or_init(|| {
+            let file = fs::File::open(&self.Path).ok()?;
+            exif::Reader::new().read_from_container(&mut io::BufReader::new(file)).ok()
+        }).as_ref()
+    }
+
+    fn audio_tags(&self) -> Option<&AudioTags>{
+        self.audio_tags.get_or_init(|| audio::read_tags(&self.Path)).as_ref()
+    }
+
+    // The content is only read once per algorithm, whatever the number of tokens using it.
+    fn hash(&self, algorithm: HashAlgorithm) -> Option<String>{
+        self.hashes.borrow_mut()
+            .entry(algorithm)
+            .or_insert_with(|| hashing::hash_file(&self.Path, algorithm).ok())
+            .clone()
+    }
+}
+
+// chrono only reports a bad specifier when formatting, it is checked once when the token is parsed.
+fn is_valid_time_format(format: &str) -> bool{
+    !format.is_empty() && !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+impl Token{
+    // Parses the text between the brackets.
+    // None when it is not a token, so the text is kept as it is written.
+    pub fn parse(representation: &str) -> Option<Result<Self, String>>{
+        if let Some((token, fallback)) = representation.split_once('|'){
+            return Token::parse(token).map(|token| token.map(|token| Token::Fallback(Box::new(token), fallback.to_string())));
+        }
+        let (name, argument) = match representation.split_once(':'){
+            Some((name, argument)) => (name, Some(argument)),
+            None => (representation, None),
+        };
+        let token = match (name, argument){
+            ("parent", None) => Ok(Token::Ancestor(1)),
+            ("parent", Some(argument)) => match argument.trim().parse::<usize>(){
+                Ok(level) if level > 0 => Ok(Token::Ancestor(level)),
+                _ => Err(format!("The level of [parent:{}] must be a number of at least 1.", argument)),
+            },
+            // with / the path moves the file into subdirectories, another separator keeps it in the name
+            ("path", separator) => Ok(Token::RelativePath(separator.unwrap_or("/").to_string())),
+            ("name", None) => Ok(Token::Name),
+            ("stem", None) => Ok(Token::Stem),
+            ("ext", None) => Ok(Token::Extension),
+            ("dup", None) => Ok(Token::DuplicateNumber),
+            ("original", None) => Ok(Token::Original),
+            ("name" | "stem" | "ext" | "dup" | "original", Some(_)) => Err(format!("[{}] does not take an argument.", name)),
+            ("mtime" | "ctime" | "atime", format) => {
+                let kind = match name{
+                    "mtime" => TimeKind::Modified,
+                    "ctime" => TimeKind::Created,
+                    _ => TimeKind::Accessed,
+                };
+                let format = format.unwrap_or(DEFAULT_TIME_FORMAT);
+                if is_valid_time_format(format){
+                    Ok(Token::Time(kind, format.to_string()))
+                }
+                else{
+                    Err(format!("[{}:{}] is not a valid time format, see the strftime specifiers like %Y-%m-%d.", name, format))
+                }
+            },
+            ("exif", Some(argument)) => {
+                let (tag, format) = match argument.split_once(':'){
+                    Some((tag, format)) => (tag.trim(), Some(format)),
+                    None => (argument.trim(), None),
+                };
+                if tag.is_empty(){
+                    Err("[exif:<tag>] needs the name of a tag, e.g. [exif:DateTimeOriginal].".to_string())
+                }
+                else if format.is_some_and(|format| !is_valid_time_format(format)){
+                    Err(format!("[exif:{}] is not a valid time format, see the strftime specifiers like %Y-%m-%d.", argument))
+                }
+                else{
+                    Ok(Token::Exif(tag.to_string(), format.map(str::to_string)))
+                }
+            },
+            ("tag", Some(argument)) => {
+                let mut parts = argument.split(':');
+                let tag = parts.next().unwrap_or_default().trim().to_lowercase();
+                let mut modifiers = vec![];
+                for modifier_name in parts{
+                    match Modifier::parse(modifier_name.trim()){
+                        Some(modifier) => modifiers.push(modifier),
+                        None => return Some(Err(format!("[tag:{}] uses the unknown modifier '{}'.", argument, modifier_name))),
+                    }
+                }
+                if tag.is_empty(){
+                    Err("[tag:<name>] needs the name of a tag, e.g. [tag:artist].".to_string())
+                }
+                else{
+                    Ok(Token::AudioTag(tag, modifiers))
+                }
+            },
+            ("hash", Some(argument)) => {
+                let (algorithm_name, length) = match argument.split_once(':'){
+                    Some((algorithm_name, length)) => (algorithm_name, Some(length)),
+                    None => (argument, None),
+                };
+                let Some(algorithm) = HashAlgorithm::parse(algorithm_name.trim()) else {
+                    return Some(Err(format!("[hash:{}] uses an unknown algorithm, use sha256, md5 or blake3.", argument)));
+                };
+                match length.map(|length| length.trim().parse::<usize>()){
+                    None => Ok(Token::Hash(algorithm, None)),
+                    Some(Ok(length)) if length > 0 && length <= algorithm.hex_length() => Ok(Token::Hash(algorithm, Some(length))),
+                    Some(_) => Err(format!("The length of [hash:{}] must be between 1 and {}.", argument, algorithm.hex_length())),
+                }
+            },
+            ("hash", None) => Err("[hash:<algorithm>] needs an algorithm: sha256, md5 or blake3.".to_string()),
+            ("tag", None) => Err("[tag:<name>] needs the name of a tag, e.g. [tag:artist].".to_string()),
+            ("exif", None) => Err("[exif:<tag>] needs the name of a tag, e.g. [exif:DateTimeOriginal].".to_string()),
+            _ => return None,
+        };
+        Some(token)
+    }
+
+    // None when the information is not available for the file.
+    pub fn value(&self, context: &FileContext) -> Option<String>{
+        let value = match self{
+            Token::Ancestor(level) => {
+                // the base directory is often ".", its name is only known from the absolute path
+                let file_path = path::absolute(&context.Path).unwrap_or(context.Path.clone());
+                file_path.ancestors().nth(*level).and_then(Path::file_name).map_or(String::new(), |name| name.to_string_lossy().to_string())
+            },
+            Token::RelativePath(separator) => {
+                let directory = context.Path.parent().unwrap_or(Path::new(""));
+                let relative = directory.strip_prefix(&context.Root).unwrap_or(directory);
+                let parts: Vec<String> = relative.components()
+                    .filter_map(|component| match component{
+                        Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                parts.join(separator)
+            },
+            Token::Name => context.Path.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string()),
+            Token::Stem => context.Path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+            Token::Extension => context.Path.extension().map_or(String::new(), |extension| extension.to_string_lossy().to_string()),
+            Token::Time(kind, format) => {
+                let metadata = fs::metadata(&context.Path).ok()?;
+                let time = match kind{
+                    TimeKind::Modified => metadata.modified(),
+                    // not every file system records the creation time
+                    TimeKind::Created => metadata.created().or(metadata.modified()),
+                    TimeKind::Accessed => metadata.accessed(),
+                }.ok()?;
+                format_time(time, format, context.Utc)
+            },
+            Token::Exif(tag, format) => {
+                // the tags of the image itself win over the ones of its thumbnail
+                let field = context.exif()?.fields()
+                    .filter(|field| field.tag.to_string().eq_ignore_ascii_case(tag))
+                    .min_by_key(|field| field.ifd_num != In::PRIMARY)?;
+                exif_value(field, format.as_deref().unwrap_or(DEFAULT_TIME_FORMAT))
+            },
+            Token::AudioTag(tag, modifiers) => {
+                let value = context.audio_tags()?.get(tag)?;
+                apply_modifiers(modifiers, &sanitize(value))
+            },
+            Token::Hash(algorithm, length) => {
+                let mut digest = context.hash(*algorithm)?;
+                digest.truncate(length.unwrap_or(digest.len()));
+                digest
+            },
+            Token::DuplicateNumber => context.Duplicate.as_ref()?.0.to_string(),
+            Token::Original => context.Duplicate.as_ref()?.1.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string()),
+            Token::Fallback(token, fallback) => {
+                return Some(token.value(context).filter(|value| !value.is_empty()).unwrap_or(fallback.clone()));
+            },
+        };
+        Some(value)
+    }
+}
+
+// Dates are written "2024:01:31 18:30:00" in EXIF, they are reformatted because : is not allowed in every file system.
+// A formatted date is used as it is, like the time tokens, so that %Y/%m sorts into folders. Other values are sanitized.
+fn exif_value(field: &exif::Field, date_format: &str) -> String{
+    match &field.value{
+        Value::Ascii(strings) => {
+            let bytes = strings.first().map_or(&[][..], |bytes| &bytes[..]);
+            if let Ok(date_time) = exif::DateTime::from_ascii(bytes){
+                let naive = NaiveDate::from_ymd_opt(date_time.year as i32, date_time.month as u32, date_time.day as u32)
+                    .and_then(|date| date.and_hms_opt(date_time.hour as u32, date_time.minute as u32, date_time.second as u32));
+                if let Some(naive) = naive{
+                    return naive.format(date_format).to_string();
+                }
+            }
+            sanitize(String::from_utf8_lossy(bytes).trim_matches(|character: char| character == '\0' || character.is_whitespace()))
+        },
+        _ => sanitize(&field.display_value().to_string()),
+    }
+}
+
+// Characters that some file systems do not allow in names.
+const RESERVED_CHARACTERS: &[char] = &['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+// Values read from inside the file can contain characters that cannot be part of a file name,
+// they become _ like control characters.
+pub fn sanitize(value: &str) -> String{
+    value.chars()
+        .map(|character| if RESERVED_CHARACTERS.contains(&character) || character.is_control() { '_' } else { character })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// The local time zone comes from the system, no time zone service is needed.
+pub fn format_time(time: SystemTime, format: &str, utc: bool) -> String{
+    if utc{
+        DateTime::<Utc>::from(time).format(format).to_string()
+    }
+    else{
+        DateTime::<Local>::from(time).format(format).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_directory;
+
+    #[test]
+    fn test_parse_token() {
+        assert_eq!(Token::parse("parent"), Some(Ok(Token::Ancestor(1))));
+        assert_eq!(Token::parse("parent:3"), Some(Ok(Token::Ancestor(3))));
+        assert_eq!(Token::parse("stem"), Some(Ok(Token::Stem)));
+        assert_eq!(Token::parse("path"), Some(Ok(Token::RelativePath("/".to_string()))));
+        assert_eq!(Token::parse("path: - "), Some(Ok(Token::RelativePath(" - ".to_string()))), "The separator is kept as written.");
+        assert!(matches!(Token::parse("parent:0"), Some(Err(_))), "There is no 0th ancestor.");
+        assert!(matches!(Token::parse("ext:x"), Some(Err(_))));
+        assert_eq!(Token::parse("1080p"), None, "Unknown names are not tokens.");
+    }
+
+    #[test]
+    fn test_token_values() {
+        let context = FileContext::new(Path::new("/shows/Lost/Season 02/Episode 5.mkv"), Path::new("/shows"));
+        assert_eq!(Token::Ancestor(1).value(&context).unwrap(), "Season 02");
+        assert_eq!(Token::Ancestor(2).value(&context).unwrap(), "Lost");
+        assert_eq!(Token::Ancestor(9).value(&context).unwrap(), "", "Levels above the root of the file system are empty.");
+        assert_eq!(Token::RelativePath("/".to_string()).value(&context).unwrap(), "Lost/Season 02");
+        assert_eq!(Token::RelativePath("_".to_string()).value(&context).unwrap(), "Lost_Season 02");
+        assert_eq!(Token::Name.value(&context).unwrap(), "Episode 5.mkv");
+        assert_eq!(Token::Stem.value(&context).unwrap(), "Episode 5");
+        assert_eq!(Token::Extension.value(&context).unwrap(), "mkv");
+
+        let context = FileContext::new(Path::new("./README"), Path::new("."));
+        assert_eq!(Token::RelativePath("/".to_string()).value(&context).unwrap(), "", "Files in the base directory have an empty path.");
+        assert_eq!(Token::Extension.value(&context).unwrap(), "");
+        assert!(!Token::Ancestor(1).value(&context).unwrap().is_empty(), "The parent of the current directory has a name.");
+    }
+
+    #[test]
+    fn test_parse_time_token() {
+        assert_eq!(Token::parse("mtime"), Some(Ok(Token::Time(TimeKind::Modified, "%Y-%m-%d".to_string()))));
+        assert_eq!(Token::parse("atime:%H:%M"), Some(Ok(Token::Time(TimeKind::Accessed, "%H:%M".to_string()))));
+        assert!(matches!(Token::parse("ctime:%Q"), Some(Err(_))), "Unknown specifiers are refused.");
+        assert!(matches!(Token::parse("mtime:"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_time_token_value() {
+        let directory = test_directory("tokens-time");
+        let file_path = directory.join("a.txt");
+        fs::write(&file_path, "a").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_706_725_800);
+        fs::File::options().write(true).open(&file_path).unwrap().set_modified(modified).unwrap();
+
+        let context = FileContext::new(&file_path, &directory).with_utc(true);
+        let token = Token::Time(TimeKind::Modified, "%Y-%m-%d_%H%M%S".to_string());
+        assert_eq!(token.value(&context), Some("2024-01-31_183000".to_string()));
+        let local = FileContext::new(&file_path, &directory);
+        assert_eq!(token.value(&local), Some(DateTime::<Local>::from(modified).format("%Y-%m-%d_%H%M%S").to_string()));
+        fs::remove_file(&file_path).unwrap();
+        assert_eq!(token.value(&context), None, "A missing file has no timestamps.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // JPEG made of an APP1 segment holding the given EXIF fields.
+    fn write_jpeg(file_path: &Path, fields: &[exif::Field]){
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields{
+            writer.push_field(field);
+        }
+        let mut tiff = io::Cursor::new(vec![]);
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+        fs::write(file_path, jpeg).unwrap();
+    }
+
+    fn ascii_field(tag: exif::Tag, value: &str) -> exif::Field{
+        exif::Field{ tag, ifd_num: In::PRIMARY, value: Value::Ascii(vec![value.as_bytes().to_vec()]) }
+    }
+
+    #[test]
+    fn test_parse_exif_and_fallback_tokens() {
+        assert_eq!(Token::parse("exif:Model"), Some(Ok(Token::Exif("Model".to_string(), None))));
+        assert_eq!(Token::parse("exif:DateTimeOriginal:%Y%m%d"), Some(Ok(Token::Exif("DateTimeOriginal".to_string(), Some("%Y%m%d".to_string())))));
+        assert!(matches!(Token::parse("exif"), Some(Err(_))), "The tag is required.");
+        assert!(matches!(Token::parse("exif:DateTime:%Q"), Some(Err(_))));
+        assert_eq!(
+            Token::parse("exif:LensModel|no lens"),
+            Some(Ok(Token::Fallback(Box::new(Token::Exif("LensModel".to_string(), None)), "no lens".to_string())))
+        );
+        assert_eq!(Token::parse("1080p|x"), None, "A fallback does not make a token of unknown names.");
+    }
+
+    #[test]
+    fn test_exif_token_value() {
+        let directory = test_directory("tokens-exif");
+        let photo = directory.join("IMG_0042.jpg");
+        write_jpeg(&photo, &[
+            ascii_field(exif::Tag::Model, "Canon EOS R/6"),
+            ascii_field(exif::Tag::DateTimeOriginal, "2024:01:31 18:30:00"),
+        ]);
+
+        let context = FileContext::new(&photo, &directory);
+        assert_eq!(Token::Exif("Model".to_string(), None).value(&context), Some("Canon EOS R_6".to_string()), "Path separators are replaced.");
+        assert_eq!(Token::Exif("model".to_string(), None).value(&context), Some("Canon EOS R_6".to_string()), "Tag names ignore case.");
+        assert_eq!(Token::Exif("DateTimeOriginal".to_string(), Some("%Y%m%d_%H%M%S".to_string())).value(&context), Some("20240131_183000".to_string()));
+        assert_eq!(Token::Exif("DateTimeOriginal".to_string(), None).value(&context), Some("2024-01-31".to_string()));
+        assert_eq!(Token::Exif("DateTimeOriginal".to_string(), Some("%Y/%m".to_string())).value(&context), Some("2024/01".to_string()), "Dates can make folders like the time tokens.");
+        assert_eq!(Token::Exif("LensModel".to_string(), None).value(&context), None);
+        let lens = Token::Fallback(Box::new(Token::Exif("LensModel".to_string(), None)), "unknown".to_string());
+        assert_eq!(lens.value(&context), Some("unknown".to_string()));
+
+        let text = directory.join("notes.txt");
+        fs::write(&text, "not an image").unwrap();
+        assert_eq!(Token::Exif("Model".to_string(), None).value(&FileContext::new(&text, &directory)), None, "Files without EXIF have no tags.");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_audio_tag_token() {
+        assert_eq!(Token::parse("tag:Track:pad2"), Some(Ok(Token::AudioTag("track".to_string(), vec![Modifier::Pad(2)]))));
+        assert!(matches!(Token::parse("tag:track:bold"), Some(Err(_))));
+        assert!(matches!(Token::parse("tag"), Some(Err(_))));
+
+        let directory = test_directory("tokens-tag");
+        let song = directory.join("01.mp3");
+        let mut tag = id3::Tag::new();
+        id3::TagLike::set_artist(&mut tag, "AC/DC");
+        id3::TagLike::set_track(&mut tag, 4);
+        let mut mp3 = vec![];
+        tag.write_to(&mut mp3, id3::Version::Id3v24).unwrap();
+        fs::write(&song, mp3).unwrap();
+        let context = FileContext::new(&song, &directory);
+        assert_eq!(Token::AudioTag("artist".to_string(), vec![]).value(&context), Some("AC_DC".to_string()));
+        assert_eq!(Token::AudioTag("track".to_string(), vec![Modifier::Pad(2)]).value(&context), Some("04".to_string()));
+        assert_eq!(Token::AudioTag("album".to_string(), vec![]).value(&context), None);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(Token::parse("hash:sha256:12"), Some(Ok(Token::Hash(HashAlgorithm::Sha256, Some(12)))));
+        assert_eq!(Token::parse("hash:md5"), Some(Ok(Token::Hash(HashAlgorithm::Md5, None))));
+        assert!(matches!(Token::parse("hash:md5:33"), Some(Err(_))), "An MD5 digest has 32 characters.");
+        assert!(matches!(Token::parse("hash:md5:0"), Some(Err(_))));
+        assert!(matches!(Token::parse("hash:crc32"), Some(Err(_))));
+        assert!(matches!(Token::parse("hash"), Some(Err(_))));
+
+        let directory = test_directory("tokens-hash");
+        let file_path = directory.join("a.txt");
+        fs::write(&file_path, "abc").unwrap();
+        let context = FileContext::new(&file_path, &directory);
+        assert_eq!(Token::Hash(HashAlgorithm::Sha256, Some(12)).value(&context), Some("ba7816bf8f01".to_string()));
+        assert_eq!(Token::Hash(HashAlgorithm::Blake3, Some(16)).value(&context), Some("6437b3ac38465133".to_string()));
+        assert_eq!(Token::Hash(HashAlgorithm::Md5, None).value(&context), Some("900150983cd24fb0d6963f7d28e17f72".to_string()));
+        fs::remove_file(&file_path).unwrap();
+        assert_eq!(Token::Hash(HashAlgorithm::Sha256, Some(12)).value(&context), Some("ba7816bf8f01".to_string()), "The digest is read once per file.");
+        assert_eq!(Token::Hash(HashAlgorithm::Sha256, None).value(&FileContext::new(&file_path, &directory)), None);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_tokens() {
+        assert_eq!(Token::parse("dup"), Some(Ok(Token::DuplicateNumber)));
+        assert_eq!(Token::parse("original"), Some(Ok(Token::Original)));
+        assert!(matches!(Token::parse("dup:2"), Some(Err(_))));
+
+        let context = FileContext::new(Path::new("photos/IMG_1 (1).jpg"), Path::new(".")).with_duplicate(2, Path::new("photos/IMG_1.jpg"));
+        assert_eq!(Token::DuplicateNumber.value(&context), Some("2".to_string()));
+        assert_eq!(Token::Original.value(&context), Some("IMG_1".to_string()));
+        let context = FileContext::new(Path::new("photos/IMG_1.jpg"), Path::new("."));
+        assert_eq!(Token::DuplicateNumber.value(&context), None, "Only duplicates have a number.");
+        assert_eq!(Token::Fallback(Box::new(Token::Original), "none".to_string()).value(&context), Some("none".to_string()));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize(" AC/DC\tlive "), "AC_DC_live");
+        assert_eq!(sanitize("Ünïcode ok"), "Ünïcode ok");
+        assert_eq!(sanitize("What? <Live>: \"Best\" *|*"), "What_ _Live__ _Best_ ___");
+    }
+}